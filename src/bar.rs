@@ -0,0 +1,527 @@
+use crate::{
+    CursorState,
+    config::{BarConfig, CursorConfig, Edge, SignalMode, WaybarConfig},
+    monitor::{Monitor, Rect},
+    visibility::{Delayed, Reason, VisibilityEngine},
+    waybar::{self, WaybarBar},
+};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+/// A Waybar instance (or group of instances) driven by the daemon, and the visibility last applied to it
+#[derive(Debug)]
+pub struct Bar {
+    /// The `[[bars]]` entry, or the defaults for the single bar driving every process
+    pub config: BarConfig,
+    /// Cached to avoid repeated lookups
+    pub pids: Vec<i32>,
+    /// The bars found in the Waybar config of those processes
+    pub detected: Vec<WaybarBar>,
+    /// How the processes are signalled, never `Auto`
+    pub mode: SignalMode,
+    /// The visibility last signalled, Waybar starts visible
+    pub visible: bool,
+    /// Why the bar has that visibility
+    pub reason: Reason,
+    /// Decides the visibility, with the overrides of the control commands
+    pub engine: VisibilityEngine,
+    /// Whether the cursor is at the bar's edge, once the reveal and hide delays are over
+    pub at_edge: Delayed,
+    /// Until when a workspace switch keeps the bar shown
+    pub peek_until: Option<Instant>,
+    /// The monitor on which the cursor revealed this bar, until the hide delay is over
+    pub cursor_zone: Option<String>,
+}
+
+impl Bar {
+    /// The bars described by the config, or a single bar driving every Waybar process
+    pub fn from_config(bars: &[BarConfig], waybar: &WaybarConfig) -> Vec<Bar> {
+        let configs = if bars.is_empty() {
+            vec![BarConfig::default()]
+        } else {
+            bars.to_vec()
+        };
+        let processes = find_waybar_processes(waybar);
+        configs
+            .into_iter()
+            .map(|config| {
+                let mut bar = Bar {
+                    config,
+                    pids: Vec::new(),
+                    detected: Vec::new(),
+                    mode: SignalMode::ShowHide,
+                    visible: true,
+                    reason: Reason::EmptyWorkspace,
+                    engine: VisibilityEngine::default(),
+                    at_edge: Delayed::default(),
+                    peek_until: None,
+                    cursor_zone: None,
+                };
+                bar.attach(&processes, waybar);
+                bar
+            })
+            .collect()
+    }
+
+    /// Carries the state of the bar this one replaces on a reload over, so Waybar processes
+    /// that were already driven are not signalled as if they had just started.
+    /// Processes the old bar did not drive start visible, and are hidden if the bar is.
+    pub fn take_over(&mut self, old: &Bar, waybar: &WaybarConfig) {
+        self.visible = old.visible;
+        self.reason = old.reason;
+        self.engine = old.engine.clone();
+        self.at_edge = old.at_edge.clone();
+        self.peek_until = old.peek_until;
+        self.cursor_zone = old.cursor_zone.clone();
+        if !self.visible {
+            for &pid in self.pids.iter().filter(|pid| !old.pids.contains(pid)) {
+                self.signal(pid, false, true, waybar);
+            }
+        }
+    }
+
+    /// Picks the processes of this bar, and reads their Waybar config when detection is enabled
+    fn attach(&mut self, processes: &[WaybarProcess], waybar: &WaybarConfig) {
+        let mine: Vec<&WaybarProcess> = processes
+            .iter()
+            .filter(|p| p.matches(&self.config))
+            .collect();
+        self.pids = mine.iter().map(|p| p.pid).collect();
+        if !waybar.detect_config {
+            self.detected.clear();
+            self.mode = waybar::signal_mode(&self.detected, waybar);
+            return;
+        }
+        let mut detected = Vec::new();
+        for process in mine {
+            let bars = waybar::locate_config(process.pid, process.config.as_deref())
+                .and_then(|path| waybar::read_config(&path))
+                .unwrap_or_default();
+            for bar in bars {
+                // Instances sharing a config file describe the same bars
+                if !detected.contains(&bar) {
+                    detected.push(bar);
+                }
+            }
+        }
+        self.detected = detected;
+        self.mode = waybar::signal_mode(&self.detected, waybar);
+    }
+
+    /// The monitor this bar lives on: configured, or else the one its Waybar config restricts it to
+    pub fn output(&self) -> Option<&str> {
+        if let Some(output) = &self.config.output {
+            return Some(output);
+        }
+        let first = self.detected.first()?.single_output()?;
+        self.detected
+            .iter()
+            .all(|bar| bar.single_output() == Some(first))
+            .then_some(first)
+    }
+
+    /// Where the cursor reveals this bar. Explicit settings win over the Waybar config,
+    /// which wins over the global thresholds.
+    pub fn zones(&self, thresholds: &CursorConfig) -> Vec<Zone> {
+        let reveal = self
+            .config
+            .reveal_threshold
+            .unwrap_or(thresholds.reveal_threshold);
+        let hide = |extent: Option<i32>| {
+            self.config
+                .hide_threshold
+                .or(extent)
+                .unwrap_or(thresholds.hide_threshold)
+                .max(reveal)
+        };
+        let detected: Vec<&WaybarBar> = self
+            .detected
+            .iter()
+            .filter(|bar| self.config.edge.is_none_or(|edge| bar.position == edge))
+            .collect();
+        if detected.is_empty() {
+            return vec![Zone {
+                edge: self.config.edge.unwrap_or_default(),
+                reveal,
+                hide: hide(None),
+                outputs: None,
+            }];
+        }
+        detected
+            .into_iter()
+            .map(|bar| Zone {
+                edge: bar.position,
+                reveal,
+                hide: hide(bar.extent()),
+                outputs: bar.outputs.clone(),
+            })
+            .collect()
+    }
+
+    /// Where the bar sits on a monitor, as deep as the zone the cursor keeps it shown in
+    pub fn areas(&self, monitor: &Monitor, thresholds: &CursorConfig) -> Vec<Rect> {
+        self.zones(thresholds)
+            .into_iter()
+            .filter(|zone| zone.shown_on(&monitor.name))
+            .map(|zone| monitor.bounds().strip(zone.edge, zone.hide))
+            .collect()
+    }
+
+    /// Whether the cursor is close enough to the bar's edge to reveal it.
+    /// Once revealed, after the reveal delay, the bar stays until the cursor moves past the
+    /// wider hide threshold or onto another monitor.
+    pub fn update_cursor_zone(&mut self, cursor: &CursorState, thresholds: &CursorConfig) -> bool {
+        let Some(output) = cursor.output.as_deref() else {
+            self.cursor_zone = None;
+            return false;
+        };
+        if self.output().is_some_and(|own| own != output) {
+            self.cursor_zone = None;
+            return false;
+        }
+        // Brushing the edge and moving on before the reveal delay is over reveals nothing
+        let revealed = self.at_edge.value() && self.cursor_zone.as_deref() == Some(output);
+        let in_zone = self.zones(thresholds).iter().any(|zone| {
+            let threshold = if revealed { zone.hide } else { zone.reveal };
+            zone.shown_on(output) && cursor.distance_to(zone.edge) <= threshold
+        });
+        // Kept through the hide delay, so coming back within the hide threshold cancels it
+        self.cursor_zone = (in_zone || revealed).then(|| output.to_string());
+        in_zone
+    }
+
+    /// Signals every Waybar process of this bar if the visibility changed
+    pub fn apply(&mut self, visible: bool, waybar: &WaybarConfig) {
+        if visible == self.visible {
+            return;
+        }
+        self.visible = visible;
+
+        // Processes found from here on were just started, and Waybar starts visible
+        let known = self.pids.clone();
+
+        // Refreshes PIDs if they were lost or not found yet
+        if self.pids.is_empty() {
+            self.attach(&find_waybar_processes(waybar), waybar);
+        }
+
+        let signalled: Vec<i32> = self
+            .pids
+            .iter()
+            .copied()
+            .filter(|&pid| self.signal(pid, visible, !known.contains(&pid), waybar))
+            .collect();
+        if signalled.len() != self.pids.len() {
+            // If a signal fails, Waybar might have restarted
+            self.attach(&find_waybar_processes(waybar), waybar);
+            for &pid in self.pids.iter().filter(|pid| !signalled.contains(pid)) {
+                self.signal(pid, visible, !known.contains(&pid), waybar);
+            }
+        }
+    }
+
+    /// Brings one process to the wanted visibility. In toggle mode the bar is assumed
+    /// to be in the opposite state, unless the process is `fresh`.
+    fn signal(&self, pid: i32, visible: bool, fresh: bool, waybar: &WaybarConfig) -> bool {
+        match self.mode {
+            SignalMode::Toggle if fresh && visible => true,
+            SignalMode::Toggle => unsafe { libc::kill(pid, waybar.hide_signal.0) == 0 },
+            _ => set_waybar_visible(pid, visible, waybar),
+        }
+    }
+}
+
+/// A strip along a screen edge where the cursor reveals a bar
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Zone {
+    /// The edge the bar is attached to, which the distances are measured from
+    pub edge: Edge,
+    /// Distance from the edge at which the bar is revealed
+    pub reveal: i32,
+    /// Distance from the edge past which the revealed bar hides again
+    pub hide: i32,
+    /// The Waybar `output` filter of the bar, `None` for every monitor
+    pub outputs: Option<Vec<String>>,
+}
+
+impl Zone {
+    /// Whether the bar shows on a monitor, and so reveals there
+    pub fn shown_on(&self, output: &str) -> bool {
+        waybar::shown_on(self.outputs.as_deref(), output)
+    }
+}
+
+/// Uses direct syscalls to signal Waybar
+fn set_waybar_visible(pid: i32, visible: bool, waybar: &WaybarConfig) -> bool {
+    let signal = if visible {
+        waybar.show_signal
+    } else {
+        waybar.hide_signal
+    };
+    unsafe { libc::kill(pid, signal.0) == 0 }
+}
+
+/// A running Waybar process, and the files it was started with
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaybarProcess {
+    /// The process id, which signals are sent to
+    pub pid: i32,
+    /// The `-c/--config` argument, as given
+    pub config: Option<PathBuf>,
+    /// The `-s/--style` argument, as given
+    pub style: Option<PathBuf>,
+    /// The full command line, arguments separated by spaces
+    pub cmdline: String,
+}
+
+impl WaybarProcess {
+    fn from_args(pid: i32, args: &[String]) -> WaybarProcess {
+        let mut config = None;
+        let mut style = None;
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            let (target, inline) = match arg.as_str() {
+                "-c" | "--config" => (&mut config, None),
+                "-s" | "--style" => (&mut style, None),
+                _ => match arg.split_once('=') {
+                    Some(("--config", value)) => (&mut config, Some(value)),
+                    Some(("--style", value)) => (&mut style, Some(value)),
+                    _ => continue,
+                },
+            };
+            *target = inline
+                .or_else(|| iter.next().map(String::as_str))
+                .map(PathBuf::from);
+        }
+        WaybarProcess {
+            pid,
+            config,
+            style,
+            cmdline: args.join(" "),
+        }
+    }
+
+    /// Whether this process is one of the instances described by `bar`
+    pub fn matches(&self, bar: &BarConfig) -> bool {
+        let path_matches = |wanted: &Option<String>, actual: &Option<PathBuf>| match wanted {
+            None => true,
+            Some(wanted) => actual.as_deref().is_some_and(|p| same_file(p, wanted)),
+        };
+        path_matches(&bar.config, &self.config)
+            && path_matches(&bar.style, &self.style)
+            && bar
+                .cmdline
+                .as_deref()
+                .is_none_or(|needle| self.cmdline.contains(needle))
+    }
+}
+
+/// Compares the path a process was started with to the one from our config.
+/// A relative path in the config matches by suffix, so `config = "config-dp1.jsonc"` works too.
+fn same_file(actual: &Path, wanted: &str) -> bool {
+    let wanted = expand_home(wanted);
+    if wanted.is_relative() {
+        return actual.ends_with(&wanted);
+    }
+    match (fs::canonicalize(actual), fs::canonicalize(&wanted)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => actual == wanted,
+    }
+}
+
+/// Replaces a leading `~/` with `$HOME`
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Finds every running Waybar process
+pub fn find_waybar_processes(waybar: &WaybarConfig) -> Vec<WaybarProcess> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let pid = path.file_name()?.to_str()?.parse::<i32>().ok()?;
+            let comm = fs::read_to_string(path.join("comm")).ok()?;
+            if !waybar.process_names.iter().any(|n| n == comm.trim()) {
+                return None;
+            }
+            // Arguments are NUL separated
+            let args: Vec<String> = fs::read(path.join("cmdline"))
+                .ok()?
+                .split(|&b| b == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect();
+            Some(WaybarProcess::from_args(pid, &args))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DelayConfig;
+
+    fn process(args: &[&str]) -> WaybarProcess {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        WaybarProcess::from_args(42, &args)
+    }
+
+    fn bar(config: Option<&str>, style: Option<&str>, cmdline: Option<&str>) -> BarConfig {
+        BarConfig {
+            config: config.map(str::to_string),
+            style: style.map(str::to_string),
+            cmdline: cmdline.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn config_and_style_from_every_argument_form() {
+        let p = process(&["waybar", "-c", "/etc/xdg/waybar/top.jsonc", "-s", "top.css"]);
+        assert_eq!(p.config, Some(PathBuf::from("/etc/xdg/waybar/top.jsonc")));
+        assert_eq!(p.style, Some(PathBuf::from("top.css")));
+        assert_eq!(p.cmdline, "waybar -c /etc/xdg/waybar/top.jsonc -s top.css");
+
+        let p = process(&[
+            "waybar",
+            "--log-level=debug",
+            "--config=a.jsonc",
+            "--style",
+            "b.css",
+        ]);
+        assert_eq!(p.config, Some(PathBuf::from("a.jsonc")));
+        assert_eq!(p.style, Some(PathBuf::from("b.css")));
+
+        // The program name is never an option, and a trailing flag has no value
+        let p = process(&["-c", "--bar", "main", "-c"]);
+        assert_eq!((p.config, p.style), (None, None));
+    }
+
+    #[test]
+    fn bars_match_by_config_style_and_command_line() {
+        let p = process(&[
+            "waybar",
+            "-c",
+            "/home/me/.config/waybar/config-dp1.jsonc",
+            "--bar",
+            "main",
+        ]);
+        assert!(p.matches(&bar(None, None, None)));
+        // Relative paths match by their last components
+        assert!(p.matches(&bar(Some("config-dp1.jsonc"), None, None)));
+        assert!(p.matches(&bar(Some("waybar/config-dp1.jsonc"), None, None)));
+        assert!(!p.matches(&bar(Some("dp1.jsonc"), None, None)));
+        assert!(!p.matches(&bar(Some("config-dp2.jsonc"), None, None)));
+        assert!(p.matches(&bar(
+            Some("/home/me/.config/waybar/config-dp1.jsonc"),
+            None,
+            None
+        )));
+        // Every matcher has to agree
+        assert!(!p.matches(&bar(Some("config-dp1.jsonc"), Some("style.css"), None)));
+        assert!(p.matches(&bar(Some("config-dp1.jsonc"), None, Some("--bar main"))));
+        assert!(!p.matches(&bar(None, None, Some("--bar side"))));
+    }
+
+    #[test]
+    fn the_hide_threshold_only_holds_a_revealed_bar() {
+        let mut bar = Bar {
+            config: BarConfig::default(),
+            pids: Vec::new(),
+            detected: Vec::new(),
+            mode: SignalMode::ShowHide,
+            visible: false,
+            reason: Reason::Windows,
+            engine: VisibilityEngine::default(),
+            at_edge: Delayed::default(),
+            peek_until: None,
+            cursor_zone: None,
+        };
+        let thresholds = CursorConfig::default();
+        let cursor = |y| CursorState {
+            output: Some("DP-1".to_string()),
+            x: 500,
+            y,
+            width: 1920,
+            height: 1080,
+        };
+
+        assert!(bar.update_cursor_zone(&cursor(0), &thresholds));
+        assert!(!bar.update_cursor_zone(&cursor(45), &thresholds));
+
+        assert!(bar.update_cursor_zone(&cursor(0), &thresholds));
+        bar.at_edge
+            .update(true, std::time::Duration::ZERO, Instant::now());
+        assert!(bar.update_cursor_zone(&cursor(45), &thresholds));
+        assert!(!bar.update_cursor_zone(&cursor(51), &thresholds));
+    }
+
+    #[test]
+    fn coming_back_during_the_hide_delay_keeps_the_bar() {
+        let mut bar = Bar {
+            config: BarConfig::default(),
+            pids: Vec::new(),
+            detected: Vec::new(),
+            mode: SignalMode::ShowHide,
+            visible: false,
+            reason: Reason::Windows,
+            engine: VisibilityEngine::default(),
+            at_edge: Delayed::default(),
+            peek_until: None,
+            cursor_zone: None,
+        };
+        let thresholds = CursorConfig::default();
+        let delays = DelayConfig {
+            hide_ms: 500,
+            ..DelayConfig::default()
+        };
+        let start = Instant::now();
+        let mut at = |y, ms| {
+            let cursor = CursorState {
+                output: Some("DP-1".to_string()),
+                x: 500,
+                y,
+                width: 1920,
+                height: 1080,
+            };
+            let in_zone = bar.update_cursor_zone(&cursor, &thresholds);
+            let now = start + std::time::Duration::from_millis(ms);
+            bar.at_edge.update(in_zone, delays.cursor(in_zone), now)
+        };
+
+        assert!(at(0, 0));
+        assert!(at(60, 100));
+        assert!(at(30, 300));
+        assert!(at(30, 800));
+
+        assert!(at(60, 900));
+        assert!(!at(60, 1400));
+        assert!(!at(30, 1500));
+    }
+
+    #[test]
+    fn absolute_paths_match_through_symlinks() {
+        let dir = std::env::temp_dir().join(format!("waybar_auto_hide-bar-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let real = dir.join("real.jsonc");
+        let link = dir.join("link.jsonc");
+        fs::write(&real, "{}").unwrap();
+        let _ = fs::remove_file(&link);
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        assert!(same_file(&link, real.to_str().unwrap()));
+        assert!(same_file(&real, link.to_str().unwrap()));
+        assert!(!same_file(&dir.join("other.jsonc"), real.to_str().unwrap()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+use crate::config::{ConfigSource, Signal};
+use std::{ffi::OsString, path::PathBuf};
+
+pub const USAGE: &str = "\
+Usage: waybar_auto_hide [OPTIONS] [COMMAND]
+
+Commands:
+  daemon                  Run the auto-hide daemon (default)
+  show                    Force the bar visible on the running daemon
+  hide                    Force the bar hidden on the running daemon
+  toggle                  Toggle the bar on the running daemon
+  pin                     Keep the bar visible on the running daemon
+  unpin                   Undo `pin`
+  peek <MS>               Show the bar for a while on the running daemon
+  status                  Print the state of the running daemon
+  subscribe               Print a JSON line each time the bar visibility changes
+  doctor                  Check that the Waybar config reacts to the signals the daemon sends
+
+Options:
+  -c, --config <PATH>         Config file to use instead of the default one
+      --reveal-threshold <PX> Distance from the edge at which the bar is revealed
+      --hide-threshold <PX>   Distance from the edge at which the bar hides again
+      --poll-interval <MS>    How often the cursor is polled near an edge
+      --process-name <NAME>   Waybar process name, may be given several times
+      --show-signal <SIGNAL>  Signal that makes Waybar show itself
+      --hide-signal <SIGNAL>  Signal that makes Waybar hide itself
+  -s, --set <KEY=VALUE>       Override any config value, e.g. cursor.hide_threshold=40
+      --check-config          Validate the config and exit
+      --print-default-config  Print the default config and exit
+  -V, --version               Print the version and exit
+  -h, --help                  Print this help and exit
+";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Daemon,
+    /// A line command forwarded to the running daemon
+    Control(String),
+    /// Streams visibility changes from the running daemon
+    Subscribe,
+    CheckConfig,
+    /// Checks the `on-sigusr1`/`on-sigusr2` actions of the Waybar config
+    Doctor,
+    PrintDefaultConfig,
+    Version,
+    Help,
+}
+
+#[derive(Debug)]
+pub struct Cli {
+    pub command: Command,
+    pub config: ConfigSource,
+}
+
+pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Cli, String> {
+    let mut command = None;
+    let mut config = ConfigSource::default();
+    let mut process_names = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let arg = arg
+            .into_string()
+            .map_err(|a| format!("invalid argument {a:?}"))?;
+
+        // Accept both `--flag value` and `--flag=value`
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next().and_then(|v| v.into_string().ok()))
+                .ok_or_else(|| format!("{flag} expects a value"))
+        };
+
+        let flag_command = match flag.as_str() {
+            "-c" | "--config" => {
+                config.path = Some(PathBuf::from(value()?));
+                None
+            }
+            "--reveal-threshold" => {
+                config
+                    .overrides
+                    .set("cursor.reveal_threshold", &number(&flag, value()?)?);
+                None
+            }
+            "--hide-threshold" => {
+                config
+                    .overrides
+                    .set("cursor.hide_threshold", &number(&flag, value()?)?);
+                None
+            }
+            "--poll-interval" => {
+                config
+                    .overrides
+                    .set("cursor.poll_interval_ms", &number(&flag, value()?)?);
+                None
+            }
+            "--process-name" => {
+                process_names.push(toml::Value::String(value()?));
+                None
+            }
+            "--show-signal" => {
+                config
+                    .overrides
+                    .set("waybar.show_signal", &signal(&flag, value()?)?);
+                None
+            }
+            "--hide-signal" => {
+                config
+                    .overrides
+                    .set("waybar.hide_signal", &signal(&flag, value()?)?);
+                None
+            }
+            "-s" | "--set" => {
+                let assignment = value()?;
+                let (key, val) = assignment
+                    .split_once('=')
+                    .ok_or_else(|| format!("{flag} expects KEY=VALUE, got `{assignment}`"))?;
+                config.overrides.set(key.trim(), val.trim());
+                None
+            }
+            "--check-config" => Some(Command::CheckConfig),
+            "--print-default-config" => Some(Command::PrintDefaultConfig),
+            "-V" | "--version" => Some(Command::Version),
+            "-h" | "--help" => Some(Command::Help),
+            "daemon" => Some(Command::Daemon),
+            "subscribe" => Some(Command::Subscribe),
+            "doctor" => Some(Command::Doctor),
+            "show" | "hide" | "toggle" | "pin" | "unpin" | "status" => {
+                Some(Command::Control(arg.clone()))
+            }
+            "peek" => {
+                let ms = args
+                    .next()
+                    .and_then(|v| v.into_string().ok())
+                    .ok_or("peek expects a duration in ms")?;
+                Some(Command::Control(format!("peek {}", number("peek", ms)?)))
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ => return Err(format!("unknown command `{arg}`")),
+        };
+
+        if let Some(new) = flag_command {
+            if command.is_some() {
+                return Err(format!("unexpected `{arg}`, only one command may be given"));
+            }
+            command = Some(new);
+        }
+    }
+
+    if !process_names.is_empty() {
+        config
+            .overrides
+            .set_value("waybar.process_names", toml::Value::Array(process_names));
+    }
+
+    Ok(Cli {
+        command: command.unwrap_or(Command::Daemon),
+        config,
+    })
+}
+
+/// Distances and durations, none of which may be negative
+fn number(flag: &str, value: String) -> Result<String, String> {
+    match value.parse::<u64>() {
+        Ok(_) => Ok(value),
+        Err(_) => Err(format!(
+            "{flag} expects a non-negative number, got `{value}`"
+        )),
+    }
+}
+
+/// Validated here so a typo is reported against the flag rather than the config key
+fn signal(flag: &str, value: String) -> Result<String, String> {
+    match Signal::parse(&value) {
+        Some(signal) => Ok(format!("\"{signal}\"")),
+        None => Err(format!(
+            "{flag} expects a signal name or number, got `{value}`"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn parse(args: &[&str]) -> Result<Cli, String> {
+        super::parse(args.iter().map(OsString::from))
+    }
+
+    fn config(cli: &Cli) -> Config {
+        Config::parse("", &cli.config.overrides).unwrap()
+    }
+
+    #[test]
+    fn flags_take_separate_or_inline_values() {
+        let cli = parse(&[
+            "--hide-threshold",
+            "40",
+            "--reveal-threshold=5",
+            "--config=/tmp/a.toml",
+            "-s",
+            "cursor.near_distance = 120",
+        ])
+        .unwrap();
+        assert_eq!(cli.command, Command::Daemon);
+        assert_eq!(cli.config.path, Some(PathBuf::from("/tmp/a.toml")));
+        let config = config(&cli);
+        assert_eq!(config.cursor.hide_threshold, 40);
+        assert_eq!(config.cursor.reveal_threshold, 5);
+        assert_eq!(config.cursor.near_distance, 120);
+    }
+
+    #[test]
+    fn process_names_accumulate() {
+        let cli = parse(&["--process-name", "waybar", "--process-name=wb", "status"]).unwrap();
+        assert_eq!(cli.command, Command::Control("status".into()));
+        assert_eq!(config(&cli).waybar.process_names, ["waybar", "wb"]);
+    }
+
+    #[test]
+    fn values_are_checked_against_their_flag() {
+        assert_eq!(
+            parse(&["peek", "-5"]).unwrap_err(),
+            "peek expects a non-negative number, got `-5`"
+        );
+        assert!(parse(&["--hide-threshold=-1"]).is_err());
+        assert!(parse(&["--show-signal", "SIGNOPE"]).is_err());
+        assert!(parse(&["--poll-interval"]).is_err());
+        assert_eq!(
+            parse(&["peek", "250"]).unwrap().command,
+            Command::Control("peek 250".into())
+        );
+    }
+
+    #[test]
+    fn only_one_command() {
+        assert_eq!(
+            parse(&["show", "hide"]).unwrap_err(),
+            "unexpected `hide`, only one command may be given"
+        );
+        assert!(parse(&["--check-config", "doctor"]).is_err());
+        assert!(parse(&["frobnicate"]).is_err());
+        assert!(parse(&["--frobnicate"]).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{
+    Event, MonitorLayout, Outputs, daemon::ZoneLayout, hotzone, hyprland::Hyprland,
+    monitor::Monitor, niri::Niri, sway::Sway, wayland::Wayland,
+};
+use serde::Deserialize;
+use std::{
+    sync::{Arc, atomic::AtomicBool, mpsc::Sender},
+    thread,
+    time::Duration,
+};
+
+/// How long to wait before reconnecting to an event stream, doubled after every failure
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Everything the daemon needs to know from the compositor
+pub trait Compositor: Send + Sync {
+    /// Shown in the logs and by `status`
+    fn name(&self) -> &'static str;
+
+    /// Whether `cursor_pos` can ever return something. Without it, the cursor is followed
+    /// with `follow_cursor` instead of being polled.
+    fn has_cursor(&self) -> bool {
+        true
+    }
+
+    /// The cursor position in the layout space
+    fn cursor_pos(&self) -> Option<CursorPos>;
+
+    /// Follows the cursor for compositors that cannot be asked where it is, sending
+    /// `Event::Cursor` when it reaches or leaves the `zones`. Over layer-shell surfaces by
+    /// default, see [`hotzone::follow`].
+    fn follow_cursor(&self, tx: Sender<Event>, zones: ZoneLayout, monitors: MonitorLayout) {
+        hotzone::follow(tx, zones, monitors);
+    }
+
+    /// The monitors and their layout, `None` when the compositor cannot be reached
+    fn monitors(&self) -> Option<Vec<Monitor>>;
+
+    /// Which monitors show windows right now
+    fn outputs(&self) -> Option<Outputs>;
+
+    /// Follows the compositor's events until the event loop is gone, sending `Event::Windows`
+    /// whenever the windows shown change and refreshing `monitors` when outputs change.
+    /// `Event::WorkspaceSwitched` is sent, where the compositor tells, before the windows
+    /// of the new workspace. `geometry` tells whether `Outputs::areas` is used at all, and
+    /// follows config reloads, so backends can skip the queries it takes.
+    /// Reconnects on its own when the connection drops.
+    fn listen(&self, tx: Sender<Event>, monitors: MonitorLayout, geometry: Arc<AtomicBool>);
+}
+
+/// A point of the layout space, shared by every monitor
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CursorPos {
+    /// Logical pixels from the left of the layout
+    pub x: i32,
+    /// Logical pixels from the top of the layout
+    pub y: i32,
+}
+
+/// Sends `Event::Windows` for the backends, only when the windows shown changed since the last one
+pub(crate) struct OutputsSender<'a> {
+    tx: &'a Sender<Event>,
+    sent: Option<Outputs>,
+}
+
+impl<'a> OutputsSender<'a> {
+    /// The first `send` always goes through, anything may have changed while disconnected
+    pub(crate) fn new(tx: &'a Sender<Event>) -> Self {
+        OutputsSender { tx, sent: None }
+    }
+
+    /// Returns false once the event loop is gone
+    pub(crate) fn send(&mut self, outputs: Outputs) -> bool {
+        if self.sent.as_ref() == Some(&outputs) {
+            return true;
+        }
+        if self.tx.send(Event::Windows(outputs.clone())).is_err() {
+            return false;
+        }
+        self.sent = Some(outputs);
+        true
+    }
+}
+
+/// Picks the backend for the session the daemon runs in
+pub fn detect() -> Arc<dyn Compositor> {
+    let set = |var: &str| std::env::var_os(var).is_some_and(|v| !v.is_empty());
+    if set("HYPRLAND_INSTANCE_SIGNATURE") {
+        Arc::new(Hyprland)
+    } else if let Some(niri) = Niri::from_env() {
+        Arc::new(niri)
+    } else if let Some(sway) = Sway::from_env() {
+        Arc::new(sway)
+    } else if Hyprland::is_running() {
+        // Hyprland also finds its instance without the variable, e.g. from a systemd service
+        Arc::new(Hyprland)
+    } else if let Some(wayland) = Wayland::from_env() {
+        Arc::new(wayland)
+    } else {
+        // Keep looking for a Hyprland that has yet to start
+        Arc::new(Hyprland)
+    }
+}
+
+/// Runs `follow` on every connection `connect` manages to open, waiting longer after each failure.
+/// `follow` returns false once the event loop is gone, which ends the loop.
+pub fn reconnecting<S>(
+    name: &str,
+    mut connect: impl FnMut() -> Option<S>,
+    mut follow: impl FnMut(S) -> bool,
+) {
+    let mut backoff = MIN_BACKOFF;
+    let mut lost = false;
+    loop {
+        if let Some(stream) = connect() {
+            if lost {
+                eprintln!("waybar_auto_hide: reconnected to {name}");
+            }
+            backoff = MIN_BACKOFF;
+            if !follow(stream) {
+                return;
+            }
+            eprintln!("waybar_auto_hide: lost the connection to {name}, reconnecting");
+            lost = true;
+        }
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
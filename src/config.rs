@@ -0,0 +1,759 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The configuration written by `--print-default-config`, kept in sync with `Config::default`
+pub const DEFAULT_CONFIG: &str = r#"[cursor]
+# The distance from the bar's edge at which the bar will activate
+reveal_threshold = 3
+# The distance from the bar's edge at which the bar will hide again
+hide_threshold = 50
+# How often the cursor position is polled while it moves or is near the edge of a bar
+poll_interval_ms = 100
+# How often it is polled while it rests away from those edges
+idle_poll_interval_ms = 500
+# The distance from the edge of a bar within which the cursor is polled quickly
+near_distance = 200
+
+[delays]
+# How long the cursor has to stay at a bar's edge before the bar shows,
+# so brushing past the edge does not flash it
+reveal_ms = 0
+# How long a revealed bar stays after the cursor leaves the edge
+hide_ms = 0
+# How long a change of the windows shown has to last before the bars follow it,
+# so flipping through workspaces does not flash them
+debounce_ms = 0
+
+[peek]
+# Show the bars for this long when the focused workspace or monitor changes, so the
+# workspace indicator can be seen even on a busy workspace. 0 disables it
+workspace_switch_ms = 0
+
+[windows]
+# Only hide a bar when a window covers it rather than whenever the workspace has windows,
+# so a small floating window elsewhere leaves the bar shown. Needs window geometry,
+# which only Hyprland reports; other compositors keep the plain rule
+intellihide = false
+
+[waybar]
+# Process names (as found in /proc/<pid>/comm) that identify Waybar
+process_names = ["waybar", ".waybar-wrapped"]
+# Signals sent to Waybar, matching its "on-sigusr1"/"on-sigusr2" settings
+show_signal = "SIGUSR2"
+hide_signal = "SIGUSR1"
+# Read the position, height, margins and outputs of the bars from the Waybar config,
+# so the hide threshold matches the bar instead of `cursor.hide_threshold`
+detect_config = true
+# "show-hide" sends show_signal and hide_signal, "toggle" only sends hide_signal and
+# tracks the state of the bar, for Waybar configs without "on-sigusr1"/"on-sigusr2".
+# "auto" picks one from the Waybar config.
+signal_mode = "show-hide"
+
+# One entry per Waybar instance, for setups running several bars.
+# Without any, every Waybar process follows the focused monitor.
+# [[bars]]
+# name = "left"
+# output = "DP-1"
+# edge = "top"
+# hide_threshold = 40
+# config = "~/.config/waybar/config-dp1.jsonc"
+"#;
+
+/// The whole config file, every section optional
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// `[cursor]`
+    pub cursor: CursorConfig,
+    /// `[delays]`
+    pub delays: DelayConfig,
+    /// `[peek]`
+    pub peek: PeekConfig,
+    /// `[windows]`
+    pub windows: WindowsConfig,
+    /// `[waybar]`
+    pub waybar: WaybarConfig,
+    /// One entry per Waybar instance. Without any, every Waybar process follows the focused monitor.
+    pub bars: Vec<BarConfig>,
+}
+
+/// Where the cursor reveals and hides the bars, and how often it is polled
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CursorConfig {
+    /// How close to the edge, in pixels, the cursor reveals a bar
+    pub reveal_threshold: i32,
+    /// How far from the edge, in pixels, the cursor has to go before a revealed bar hides
+    pub hide_threshold: i32,
+    /// Used while the cursor moves or is within `near_distance` of the edge of a bar
+    pub poll_interval_ms: u64,
+    /// Used while the cursor rests away from the edges, never shorter than `poll_interval_ms`
+    pub idle_poll_interval_ms: u64,
+    /// How close to the edge of a bar, in pixels, the cursor is polled at `poll_interval_ms`
+    pub near_distance: i32,
+}
+
+/// Delays in milliseconds, all 0 to follow every change right away
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DelayConfig {
+    /// Before the cursor at the edge reveals a bar
+    pub reveal_ms: u64,
+    /// Before the cursor away from the edge hides a bar
+    pub hide_ms: u64,
+    /// Applies to changes of the windows shown, not to the cursor
+    pub debounce_ms: u64,
+}
+
+impl DelayConfig {
+    /// How long the cursor has to be at the edge, or away from it, before the bar follows
+    pub fn cursor(&self, at_edge: bool) -> Duration {
+        Duration::from_millis(if at_edge {
+            self.reveal_ms
+        } else {
+            self.hide_ms
+        })
+    }
+
+    /// How long the windows shown have to settle before the bars follow
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+}
+
+/// Temporary reveals, in milliseconds
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PeekConfig {
+    /// How long a bar shows after its monitor switched workspaces
+    pub workspace_switch_ms: u64,
+}
+
+impl PeekConfig {
+    /// `workspace_switch_ms` as a duration
+    pub fn workspace_switch(&self) -> Duration {
+        Duration::from_millis(self.workspace_switch_ms)
+    }
+}
+
+/// Which windows hide the bars
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowsConfig {
+    /// Only windows overlapping a bar hide it
+    pub intellihide: bool,
+}
+
+/// How the Waybar processes are found and signalled
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WaybarConfig {
+    /// The process names Waybar runs under
+    pub process_names: Vec<String>,
+    /// Sent to show a bar
+    pub show_signal: Signal,
+    /// Sent to hide a bar
+    pub hide_signal: Signal,
+    /// Reads the position, size and outputs of the bars from the Waybar config
+    pub detect_config: bool,
+    /// How the signals are understood by Waybar
+    pub signal_mode: SignalMode,
+}
+
+/// How Waybar is told to show or hide itself
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignalMode {
+    /// `show-hide`, unless the Waybar config lacks the matching `on-sigusr` actions
+    /// and `hide_signal` toggles the bar, then `toggle`
+    Auto,
+    /// `show_signal` shows the bar and `hide_signal` hides it
+    #[default]
+    ShowHide,
+    /// `hide_signal` toggles the bar, and the daemon keeps track of its state
+    Toggle,
+}
+
+/// A `[[bars]]` entry
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BarConfig {
+    /// How the bar is referred to in `status` and `subscribe`
+    pub name: Option<String>,
+    /// The monitor this bar lives on, its visibility only depends on that monitor.
+    /// Unset, the bar follows the focused monitor.
+    pub output: Option<String>,
+    /// The screen edge the bar is attached to, the cursor reveals it there.
+    /// Unset, it is read from the Waybar config, or else the top edge.
+    pub edge: Option<Edge>,
+    /// Overrides `cursor.reveal_threshold` for this bar
+    pub reveal_threshold: Option<i32>,
+    /// Overrides `cursor.hide_threshold` and the height read from the Waybar config for this bar
+    pub hide_threshold: Option<i32>,
+    /// The `--config` file the Waybar instance was started with
+    pub config: Option<String>,
+    /// The `--style` file the Waybar instance was started with
+    pub style: Option<String>,
+    /// A substring of the Waybar command line identifying the instance
+    pub cmdline: Option<String>,
+}
+
+/// A screen edge a bar can be attached to
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    /// The default
+    #[default]
+    Top,
+    /// The bottom edge
+    Bottom,
+    /// The left edge
+    Left,
+    /// The right edge
+    Right,
+}
+
+impl BarConfig {
+    /// Whether this bar only controls some of the Waybar processes
+    pub fn has_matcher(&self) -> bool {
+        self.config.is_some() || self.style.is_some() || self.cmdline.is_some()
+    }
+
+    /// Whether both bars pick the same Waybar processes
+    pub fn same_matcher(&self, other: &BarConfig) -> bool {
+        (&self.config, &self.style, &self.cmdline) == (&other.config, &other.style, &other.cmdline)
+    }
+}
+
+impl Default for CursorConfig {
+    fn default() -> Self {
+        CursorConfig {
+            reveal_threshold: 3,
+            hide_threshold: 50,
+            poll_interval_ms: 100,
+            idle_poll_interval_ms: 500,
+            near_distance: 200,
+        }
+    }
+}
+
+impl Default for WaybarConfig {
+    fn default() -> Self {
+        WaybarConfig {
+            process_names: vec!["waybar".into(), ".waybar-wrapped".into()],
+            show_signal: Signal(libc::SIGUSR2),
+            hide_signal: Signal(libc::SIGUSR1),
+            detect_config: true,
+            signal_mode: SignalMode::ShowHide,
+        }
+    }
+}
+
+/// A POSIX signal number, written in the config as a name ("SIGUSR1", "USR1", "SIGRTMIN+2") or a number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signal(pub i32);
+
+const SIGNAL_NAMES: &[(&str, i32)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("TERM", libc::SIGTERM),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+];
+
+impl Signal {
+    /// Reads a signal name or number, `None` for unknown names and numbers out of range
+    pub fn parse(name: &str) -> Option<Signal> {
+        let name = name.trim();
+        if let Ok(num) = name.parse::<i32>() {
+            return Signal::checked(num);
+        }
+        let bare = name.strip_prefix("SIG").unwrap_or(name);
+        if let Some(offset) = bare.strip_prefix("RTMIN") {
+            let offset = match offset.strip_prefix('+') {
+                Some(n) => n.parse::<i32>().ok()?,
+                None if offset.is_empty() => 0,
+                None => return None,
+            };
+            return Signal::checked(libc::SIGRTMIN() + offset);
+        }
+        SIGNAL_NAMES
+            .iter()
+            .find(|(n, _)| *n == bare)
+            .map(|&(_, num)| Signal(num))
+    }
+
+    fn checked(num: i32) -> Option<Signal> {
+        (1..=libc::SIGRTMAX()).contains(&num).then_some(Signal(num))
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((name, _)) = SIGNAL_NAMES.iter().find(|(_, n)| *n == self.0) {
+            return write!(f, "SIG{name}");
+        }
+        if self.0 >= libc::SIGRTMIN() {
+            return write!(f, "SIGRTMIN+{}", self.0 - libc::SIGRTMIN());
+        }
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Signal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(i32),
+            Name(String),
+        }
+        let parsed = match Raw::deserialize(deserializer)? {
+            Raw::Number(num) => Signal::checked(num),
+            Raw::Name(name) => Signal::parse(&name),
+        };
+        parsed.ok_or_else(|| {
+            serde::de::Error::custom("expected a signal name like \"SIGUSR1\" or a signal number")
+        })
+    }
+}
+
+/// Why the config could not be loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read
+    Io(PathBuf, io::Error),
+    /// The file is not valid TOML or does not match the config layout
+    Parse(Option<PathBuf>, toml::de::Error),
+    /// A command line override is malformed
+    Override(String),
+    /// A value is out of range or contradicts another
+    Invalid {
+        /// The file, unknown while parsing a string
+        path: Option<PathBuf>,
+        /// The dotted key of the value
+        key: String,
+        /// The line of the value in the file, if it is there at all
+        line: Option<usize>,
+        /// What is wrong with it
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |path: &Option<PathBuf>| match path {
+            Some(p) => p.display().to_string(),
+            None => "<config>".to_string(),
+        };
+        match self {
+            ConfigError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            ConfigError::Parse(path, err) => write!(f, "{}: {err}", name(path)),
+            ConfigError::Override(message) => write!(f, "command line: {message}"),
+            ConfigError::Invalid {
+                path,
+                key,
+                line,
+                message,
+            } => match line {
+                Some(line) => write!(f, "{}:{line}: `{key}` {message}", name(path)),
+                None => write!(f, "{}: `{key}` {message}", name(path)),
+            },
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/waybar_auto_hide/config.toml`, falling back to `~/.config`
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("waybar_auto_hide").join("config.toml"))
+    }
+
+    /// Loads the config from an explicit path, or from the default path if one exists,
+    /// then applies the command line overrides on top of it.
+    /// A missing default file is not an error: the built-in defaults are used instead.
+    pub fn load(explicit: Option<&Path>, overrides: &Overrides) -> Result<Config, ConfigError> {
+        let path = match explicit {
+            Some(p) => Some(p.to_path_buf()),
+            None => Config::default_path().filter(|p| p.exists()),
+        };
+        let source = match &path {
+            Some(p) => fs::read_to_string(p).map_err(|e| ConfigError::Io(p.clone(), e))?,
+            None => String::new(),
+        };
+        Config::parse(&source, overrides).map_err(|e| match &path {
+            Some(p) => e.with_path(p),
+            None => e,
+        })
+    }
+
+    /// Parses a config file's contents, applies the overrides and validates the result
+    pub fn parse(source: &str, overrides: &Overrides) -> Result<Config, ConfigError> {
+        // Parsed on its own first, so that errors in the file keep their line numbers
+        let mut config: Config = toml::from_str(source).map_err(|e| ConfigError::Parse(None, e))?;
+        if !overrides.0.is_empty() {
+            let file: toml::Table = toml::from_str(source).unwrap_or_default();
+            config = overrides.apply(file)?;
+        }
+        config.validate(source).map_err(|err| match err {
+            // Blame the command line unless the file alone is at fault
+            ConfigError::Invalid {
+                key, line, message, ..
+            } if overrides.covers(&key) || (line.is_none() && !overrides.0.is_empty()) => {
+                ConfigError::Override(format!("`{key}` {message}"))
+            }
+            err => err,
+        })?;
+        Ok(config)
+    }
+
+    fn validate(&self, source: &str) -> Result<(), ConfigError> {
+        let invalid = |key: &str, message: String| ConfigError::Invalid {
+            path: None,
+            key: key.to_string(),
+            line: find_key_line(source, key),
+            message,
+        };
+        let cursor = &self.cursor;
+        if cursor.reveal_threshold < 0 {
+            return Err(invalid(
+                "cursor.reveal_threshold",
+                "must not be negative".into(),
+            ));
+        }
+        if cursor.hide_threshold < cursor.reveal_threshold {
+            return Err(invalid(
+                "cursor.hide_threshold",
+                format!(
+                    "must be at least cursor.reveal_threshold ({})",
+                    cursor.reveal_threshold
+                ),
+            ));
+        }
+        if cursor.poll_interval_ms == 0 {
+            return Err(invalid(
+                "cursor.poll_interval_ms",
+                "must be greater than 0".into(),
+            ));
+        }
+        if cursor.near_distance < 0 {
+            return Err(invalid(
+                "cursor.near_distance",
+                "must not be negative".into(),
+            ));
+        }
+        let waybar = &self.waybar;
+        if waybar.process_names.iter().all(|n| n.trim().is_empty()) {
+            return Err(invalid(
+                "waybar.process_names",
+                "must contain at least one process name".into(),
+            ));
+        }
+        if waybar.show_signal == waybar.hide_signal {
+            return Err(invalid(
+                "waybar.hide_signal",
+                format!(
+                    "must differ from waybar.show_signal ({})",
+                    waybar.show_signal
+                ),
+            ));
+        }
+        for (idx, bar) in self.bars.iter().enumerate() {
+            let fields = [
+                ("name", &bar.name),
+                ("output", &bar.output),
+                ("config", &bar.config),
+                ("style", &bar.style),
+                ("cmdline", &bar.cmdline),
+            ];
+            for (field, value) in fields {
+                if value.as_deref() == Some("") {
+                    return Err(invalid(
+                        &format!("bars[{idx}].{field}"),
+                        "must not be empty".into(),
+                    ));
+                }
+            }
+            // Bars sharing the same Waybar processes would fight over their visibility
+            if self.bars.len() > 1 && !bar.has_matcher() {
+                return Err(invalid(
+                    &format!("bars[{idx}].config"),
+                    "(or `style`, `cmdline`) is required when more than one bar is configured"
+                        .into(),
+                ));
+            }
+            if bar.reveal_threshold.is_some_and(|t| t < 0) {
+                return Err(invalid(
+                    &format!("bars[{idx}].reveal_threshold"),
+                    "must not be negative".into(),
+                ));
+            }
+            if let (Some(reveal), Some(hide)) = (bar.reveal_threshold, bar.hide_threshold)
+                && hide < reveal
+            {
+                return Err(invalid(
+                    &format!("bars[{idx}].hide_threshold"),
+                    format!("must be at least bars[{idx}].reveal_threshold ({reveal})"),
+                ));
+            }
+            if bar.name.is_some() && self.bars[..idx].iter().any(|b| b.name == bar.name) {
+                return Err(invalid(
+                    &format!("bars[{idx}].name"),
+                    "is already used by another bar".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the config comes from, kept around so it can be loaded again on reload
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSource {
+    /// Set by `--config`, otherwise the default path is used
+    pub path: Option<PathBuf>,
+    /// Set by `--set`
+    pub overrides: Overrides,
+}
+
+impl ConfigSource {
+    /// Reads the file again and applies the overrides
+    pub fn load(&self) -> Result<Config, ConfigError> {
+        Config::load(self.path.as_deref(), &self.overrides)
+    }
+
+    /// The file that is (or would be) read, whether it exists or not
+    pub fn file(&self) -> Option<PathBuf> {
+        self.path.clone().or_else(Config::default_path)
+    }
+}
+
+/// Values given on the command line as dotted keys, applied on top of the file on every (re)load
+#[derive(Debug, Clone, Default)]
+pub struct Overrides(Vec<(String, toml::Value)>);
+
+impl Overrides {
+    /// Records `key = value`, where the value is TOML syntax (`5`, `["a", "b"]`) or a bare string
+    pub fn set(&mut self, key: &str, value: &str) {
+        let value = value
+            .parse::<toml::Value>()
+            .unwrap_or_else(|_| toml::Value::String(value.to_string()));
+        self.set_value(key, value);
+    }
+
+    /// Records `key = value` for an already parsed value
+    pub fn set_value(&mut self, key: &str, value: toml::Value) {
+        self.0.push((key.to_string(), value));
+    }
+
+    fn apply(&self, file: toml::Table) -> Result<Config, ConfigError> {
+        let mut table = file.clone();
+        for (key, value) in &self.0 {
+            insert_dotted(&mut table, key, value.clone());
+        }
+        table.try_into().map_err(|err: toml::de::Error| {
+            // Errors from a merged table carry no location, so find the culprit by applying them one by one
+            let culprit = self.0.iter().find(|(key, value)| {
+                let mut single = file.clone();
+                insert_dotted(&mut single, key, value.clone());
+                single.try_into::<Config>().is_err()
+            });
+            match culprit {
+                Some((key, _)) => ConfigError::Override(format!("`{key}` {}", err.message())),
+                None => ConfigError::Override(err.message().to_string()),
+            }
+        })
+    }
+
+    fn covers(&self, key: &str) -> bool {
+        self.0.iter().any(|(k, _)| k == key)
+    }
+}
+
+fn insert_dotted(table: &mut toml::Table, dotted: &str, value: toml::Value) {
+    match dotted.split_once('.') {
+        None => {
+            table.insert(dotted.to_string(), value);
+        }
+        Some((head, rest)) => {
+            let entry = table
+                .entry(head)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if !entry.is_table() {
+                *entry = toml::Value::Table(toml::Table::new());
+            }
+            if let toml::Value::Table(inner) = entry {
+                insert_dotted(inner, rest, value);
+            }
+        }
+    }
+}
+
+impl ConfigError {
+    fn with_path(self, path: &Path) -> ConfigError {
+        let path = Some(path.to_path_buf());
+        match self {
+            ConfigError::Parse(_, err) => ConfigError::Parse(path, err),
+            ConfigError::Invalid {
+                key, line, message, ..
+            } => ConfigError::Invalid {
+                path,
+                key,
+                line,
+                message,
+            },
+            other => other,
+        }
+    }
+}
+
+/// Finds the 1-based line on which a dotted `section.key` is assigned.
+/// Entries of a `[[section]]` array are addressed as `section[index].key`.
+/// A key that is not set points at the header of its section instead.
+fn find_key_line(source: &str, dotted: &str) -> Option<usize> {
+    let (section, key) = dotted.rsplit_once('.').unwrap_or(("", dotted));
+    let (section, index) = match section.strip_suffix(']').and_then(|s| s.split_once('[')) {
+        Some((name, index)) => (name, index.parse::<usize>().ok()?),
+        None => (section, 0),
+    };
+    let mut current = "";
+    let mut seen: usize = 0;
+    let mut header_line = None;
+    for (idx, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let header = header.trim();
+            current = match header.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+                Some(array) => {
+                    let array = array.trim();
+                    if array == section {
+                        seen += 1;
+                    }
+                    array
+                }
+                None => header,
+            };
+            if current == section && seen.saturating_sub(1) == index {
+                header_line = Some(idx + 1);
+            }
+            continue;
+        }
+        if current == section
+            && seen.saturating_sub(1) == index
+            && let Some((lhs, _)) = line.split_once('=')
+            && lhs.trim().trim_matches('"') == key
+        {
+            return Some(idx + 1);
+        }
+    }
+    header_line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_matches_the_defaults() {
+        assert_eq!(
+            Config::parse(DEFAULT_CONFIG, &Overrides::default()).unwrap(),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn key_lines() {
+        let source = "\
+[cursor]
+reveal_threshold = 3
+\"hide_threshold\" = 50
+
+[[bars]]
+name = \"left\"
+config = \"left.jsonc\"
+
+[[bars]]
+name = \"right\"
+";
+        assert_eq!(find_key_line(source, "cursor.reveal_threshold"), Some(2));
+        assert_eq!(find_key_line(source, "cursor.hide_threshold"), Some(3));
+        assert_eq!(find_key_line(source, "bars[0].config"), Some(7));
+        assert_eq!(find_key_line(source, "bars[1].name"), Some(10));
+        // Not set, so the header of the table it belongs in
+        assert_eq!(find_key_line(source, "bars[1].config"), Some(9));
+        assert_eq!(find_key_line(source, "cursor.near_distance"), Some(1));
+        assert_eq!(find_key_line(source, "waybar.hide_signal"), None);
+        assert_eq!(find_key_line(source, "bars[2].name"), None);
+    }
+
+    #[test]
+    fn errors_name_the_key_and_line() {
+        let err = Config::parse(
+            "[cursor]\nreveal_threshold = 10\nhide_threshold = 5\n",
+            &Overrides::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "<config>:3: `cursor.hide_threshold` must be at least cursor.reveal_threshold (10)"
+        );
+
+        let err = Config::parse(
+            "[[bars]]\nname = \"left\"\n[[bars]]\nname = \"right\"\nconfig = \"b.jsonc\"\n",
+            &Overrides::default(),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("<config>:1: `bars[0].config`"),
+            "{err}"
+        );
+
+        let err = Config::parse(
+            "[waybar]\nhide_signal = \"SIGNOPE\"\n",
+            &Overrides::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+    }
+
+    #[test]
+    fn overrides_apply_on_top_of_the_file() {
+        let mut overrides = Overrides::default();
+        overrides.set("cursor.hide_threshold", "80");
+        overrides.set("waybar.process_names", r#"["waybar", "wb"]"#);
+        let config = Config::parse("[cursor]\nhide_threshold = 40\n", &overrides).unwrap();
+        assert_eq!(config.cursor.hide_threshold, 80);
+        assert_eq!(config.waybar.process_names, ["waybar", "wb"]);
+
+        // Invalid values are blamed on the command line
+        let mut overrides = Overrides::default();
+        overrides.set("cursor.reveal_threshold", "-1");
+        let err = Config::parse("", &overrides).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command line: `cursor.reveal_threshold` must not be negative"
+        );
+        let mut overrides = Overrides::default();
+        overrides.set("waybar.show_signal", "SIGNOPE");
+        let err = Config::parse("", &overrides).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("command line: `waybar.show_signal`"),
+            "{err}"
+        );
+    }
+}
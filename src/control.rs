@@ -0,0 +1,162 @@
+use crate::Event;
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+/// A line command accepted on the control socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// Shows the bar until the automatic decision changes
+    Show,
+    /// Hides the bar until the automatic decision changes
+    Hide,
+    /// Flips the current visibility until the automatic decision changes
+    Toggle,
+    /// Keeps the bar visible until `Unpin`
+    Pin,
+    Unpin,
+    /// Shows the bar for a while, then returns to the automatic decision
+    Peek(Duration),
+    Status,
+    /// Streams one JSON line per visibility change until the client disconnects
+    Subscribe,
+}
+
+impl FromStr for ControlCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let command = match words.next().unwrap_or_default() {
+            "show" => ControlCommand::Show,
+            "hide" => ControlCommand::Hide,
+            "toggle" => ControlCommand::Toggle,
+            "pin" => ControlCommand::Pin,
+            "unpin" => ControlCommand::Unpin,
+            "status" => ControlCommand::Status,
+            "subscribe" => ControlCommand::Subscribe,
+            "peek" => {
+                let ms = words.next().ok_or("peek expects a duration in ms")?;
+                let ms = ms
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid peek duration `{ms}`"))?;
+                ControlCommand::Peek(Duration::from_millis(ms))
+            }
+            "" => return Err("empty command".into()),
+            other => return Err(format!("unknown command `{other}`")),
+        };
+        match words.next() {
+            Some(extra) => Err(format!("unexpected argument `{extra}`")),
+            None => Ok(command),
+        }
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/waybar_auto_hide.sock`, where the daemon listens for commands
+pub fn socket_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR").filter(|v| !v.is_empty())?;
+    Some(PathBuf::from(dir).join("waybar_auto_hide.sock"))
+}
+
+/// Sends a single line command to the running daemon and returns its reply
+pub fn request(command: &str) -> io::Result<String> {
+    let mut stream = connect()?;
+    stream.write_all(format!("{command}\n").as_bytes())?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+fn connect() -> io::Result<UnixStream> {
+    let path = socket_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))?;
+    UnixStream::connect(&path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("no running daemon at {}: {e}", path.display()),
+        )
+    })
+}
+
+/// Sends `subscribe` to the running daemon and copies every update to `out` as it arrives
+pub fn subscribe(out: &mut impl Write) -> io::Result<()> {
+    let mut stream = connect()?;
+    stream.write_all(b"subscribe\n")?;
+    for line in BufReader::new(stream).lines() {
+        writeln!(out, "{}", line?)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Listens on the control socket and forwards every command to the event loop,
+/// which answers through the channel attached to the event
+pub fn spawn_control_server(tx: Sender<Event>) {
+    let Some(path) = socket_path() else {
+        eprintln!("waybar_auto_hide: XDG_RUNTIME_DIR is not set, control socket disabled");
+        return;
+    };
+    if UnixStream::connect(&path).is_ok() {
+        eprintln!(
+            "waybar_auto_hide: another instance is listening on {}, control socket disabled",
+            path.display()
+        );
+        return;
+    }
+    // Left behind by a previous instance that did not exit cleanly
+    fs::remove_file(&path).ok();
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("waybar_auto_hide: cannot bind {}: {err}", path.display());
+            return;
+        }
+    };
+
+    thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            let tx = tx.clone();
+            thread::spawn(move || handle_client(stream, tx));
+        }
+    });
+}
+
+fn handle_client(stream: UnixStream, tx: Sender<Event>) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match line.parse::<ControlCommand>() {
+            Ok(command) => {
+                let (reply_tx, reply_rx) = mpsc::channel();
+                if tx.send(Event::Control(command, reply_tx)).is_err() {
+                    return;
+                }
+                if command == ControlCommand::Subscribe {
+                    for update in reply_rx {
+                        if writeln!(writer, "{update}").is_err() {
+                            return;
+                        }
+                    }
+                    return;
+                }
+                reply_rx.recv().unwrap_or_default()
+            }
+            Err(err) => format!("error: {err}"),
+        };
+        if writeln!(writer, "{reply}").is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,526 @@
+use crate::{
+    bar::{Bar, Zone},
+    compositor::{self, Compositor},
+    config::{Config, ConfigError, ConfigSource, CursorConfig, Edge, SignalMode, WaybarConfig},
+    control::{self, ControlCommand},
+    monitor::{self, Monitor, Rect},
+    reload,
+    visibility::{Decision, Inputs, Reason},
+    waybar,
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Runs the daemon: follows the compositor and drives the bars until the process is killed.
+/// Only returns early when the config cannot be loaded.
+///
+/// Blocks `SIGHUP` in the calling thread, which has to be done before any other thread is spawned
+/// for reloads on `SIGHUP` to work.
+pub fn run(source: ConfigSource) -> Result<(), ConfigError> {
+    // Has to happen before any thread is spawned so they all inherit the mask
+    let sighup = reload::block_sighup();
+
+    let mut config = source.load()?;
+
+    let (tx, rx) = mpsc::channel::<Event>();
+
+    let compositor = compositor::detect();
+    let mut cursor = CursorState::default();
+    let mut outputs = compositor.outputs().unwrap_or_default();
+    let mut bars = Bar::from_config(&config.bars, &config.waybar);
+    lint_bars(&bars, &config.waybar);
+
+    // Overrides set through the control socket
+    let mut pinned = false;
+    let mut peek_until: Option<Instant> = None;
+    // Windows changes waiting out `delays.debounce_ms`, and when they apply
+    let mut pending_outputs: Option<(Outputs, Instant)> = None;
+    let mut subscribers: Vec<Sender<String>> = Vec::new();
+
+    // Shared with the cursor thread so reloads apply without restarting it
+    let cursor_config = Arc::new(RwLock::new(config.cursor.clone()));
+    // Only refreshed when outputs change, and on compositor config reloads
+    let monitors: MonitorLayout = Arc::new(RwLock::new(compositor.monitors().unwrap_or_default()));
+    // Where the bars reveal, the only places the cursor thread polls quickly near
+    let bar_zones: ZoneLayout = Arc::new(RwLock::new(zones(&bars, &config.cursor)));
+
+    if compositor.has_cursor() {
+        spawn_mouse_position_updated(
+            tx.clone(),
+            compositor.clone(),
+            cursor_config.clone(),
+            bar_zones.clone(),
+            monitors.clone(),
+        );
+    } else {
+        let (compositor, tx) = (compositor.clone(), tx.clone());
+        let (zones, monitors) = (bar_zones.clone(), monitors.clone());
+        thread::spawn(move || compositor.follow_cursor(tx, zones, monitors));
+    }
+    // Only intellihide needs to know where windows are
+    let geometry = Arc::new(AtomicBool::new(config.windows.intellihide));
+    {
+        let (compositor, tx, monitors) = (compositor.clone(), tx.clone(), monitors.clone());
+        let geometry = geometry.clone();
+        thread::spawn(move || compositor.listen(tx, monitors, geometry));
+    }
+    reload::spawn_config_watcher(source.clone(), tx.clone());
+    reload::spawn_sighup_listener(sighup, source, tx.clone());
+    control::spawn_control_server(tx.clone());
+
+    tx.send(Event::Cursor(CursorState::default())).ok();
+
+    loop {
+        // Wakes up on its own when a peek runs out or a delay is over
+        let deadline = [peek_until, pending_outputs.as_ref().map(|(_, at)| *at)]
+            .into_iter()
+            .chain(bars.iter().map(|bar| bar.at_edge.deadline()))
+            .chain(bars.iter().map(|bar| bar.peek_until))
+            .flatten()
+            .min();
+        let event = match deadline {
+            Some(deadline) => {
+                match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(event) => Some(event),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            None => match rx.recv() {
+                Ok(event) => Some(event),
+                Err(_) => break,
+            },
+        };
+
+        let now = Instant::now();
+        if peek_until.is_some_and(|until| until <= now) {
+            peek_until = None;
+        }
+        for bar in &mut bars {
+            if bar.peek_until.is_some_and(|until| until <= now) {
+                bar.peek_until = None;
+            }
+        }
+        if pending_outputs.as_ref().is_some_and(|(_, at)| *at <= now)
+            && let Some((val, _)) = pending_outputs.take()
+        {
+            outputs = val;
+        }
+
+        // Every event goes through the decisions below, even one that changes nothing itself:
+        // the timers that just ran out have to be decided on
+        match event {
+            None => {}
+            Some(Event::Cursor(val)) => cursor = val,
+            Some(Event::Windows(val)) => {
+                let debounce = config.delays.debounce();
+                if debounce.is_zero() {
+                    outputs = val;
+                } else {
+                    // Going back to what the bars already follow cancels the change
+                    pending_outputs = (val != outputs).then(|| (val, now + debounce));
+                }
+            }
+            Some(Event::WorkspaceSwitched(monitor)) => {
+                let duration = config.peek.workspace_switch();
+                // Bars bound to another monitor did not see anything change
+                for bar in &mut bars {
+                    if !duration.is_zero()
+                        && (monitor.is_none()
+                            || bar.output().is_none_or(|o| Some(o) == monitor.as_deref()))
+                    {
+                        bar.peek_until = Some(now + duration);
+                    }
+                }
+            }
+            Some(Event::ConfigReloaded(new_config)) if *new_config == config => {}
+            Some(Event::ConfigReloaded(new_config)) => {
+                *cursor_config.write().unwrap() = new_config.cursor.clone();
+                geometry.store(new_config.windows.intellihide, Ordering::Relaxed);
+                // The processes are looked for, and their Waybar config read, again
+                if new_config.bars != config.bars
+                    || new_config.waybar.process_names != config.waybar.process_names
+                    || new_config.waybar.detect_config != config.waybar.detect_config
+                {
+                    let old = std::mem::replace(
+                        &mut bars,
+                        Bar::from_config(&new_config.bars, &new_config.waybar),
+                    );
+                    for bar in &mut bars {
+                        if let Some(previous) =
+                            old.iter().find(|o| o.config.same_matcher(&bar.config))
+                        {
+                            bar.take_over(previous, &new_config.waybar);
+                        }
+                    }
+                    lint_bars(&bars, &new_config.waybar);
+                } else if new_config.waybar != config.waybar {
+                    for bar in &mut bars {
+                        bar.mode = waybar::signal_mode(&bar.detected, &new_config.waybar);
+                    }
+                    lint_bars(&bars, &new_config.waybar);
+                }
+                config = *new_config;
+                eprintln!("waybar_auto_hide: config reloaded");
+            }
+            Some(Event::Control(command, reply)) => {
+                // Subscriptions are answered as the bars change rather than once
+                let answer = match command {
+                    ControlCommand::Show => {
+                        bars.iter_mut().for_each(|bar| bar.engine.force(true));
+                        Some("ok".to_string())
+                    }
+                    ControlCommand::Hide => {
+                        (pinned, peek_until) = (false, None);
+                        bars.iter_mut().for_each(|bar| bar.peek_until = None);
+                        bars.iter_mut().for_each(|bar| bar.engine.force(false));
+                        Some("ok".to_string())
+                    }
+                    ControlCommand::Toggle => {
+                        (pinned, peek_until) = (false, None);
+                        bars.iter_mut().for_each(|bar| bar.peek_until = None);
+                        bars.iter_mut()
+                            .for_each(|bar| bar.engine.force(!bar.visible));
+                        Some("ok".to_string())
+                    }
+                    ControlCommand::Pin => {
+                        pinned = true;
+                        Some("ok".to_string())
+                    }
+                    ControlCommand::Unpin => {
+                        pinned = false;
+                        Some("ok".to_string())
+                    }
+                    ControlCommand::Peek(duration) => {
+                        peek_until = Some(Instant::now() + duration);
+                        Some("ok".to_string())
+                    }
+                    ControlCommand::Subscribe => {
+                        // The current state first, then one line per change
+                        if bars.iter().all(|bar| reply.send(transition(bar)).is_ok()) {
+                            subscribers.push(reply.clone());
+                        }
+                        None
+                    }
+                    ControlCommand::Status => Some(
+                        serde_json::to_string(&Status {
+                            compositor: compositor.name(),
+                            cursor: &cursor,
+                            monitors: monitors
+                                .read()
+                                .unwrap()
+                                .iter()
+                                .map(MonitorStatus::from)
+                                .collect(),
+                            outputs: &outputs,
+                            pinned,
+                            peeking: peek_until.is_some(),
+                            bars: bars
+                                .iter()
+                                .map(|bar| BarStatus::new(bar, &config.cursor))
+                                .collect(),
+                        })
+                        .unwrap_or_default(),
+                    ),
+                };
+                if let Some(answer) = answer {
+                    reply.send(answer).ok();
+                }
+            }
+        }
+
+        for bar in &mut bars {
+            let in_zone = bar.update_cursor_zone(&cursor, &config.cursor);
+            let at_edge = bar
+                .at_edge
+                .update(in_zone, config.delays.cursor(in_zone), now);
+            let output = bar.output().map(str::to_string);
+            let bar_areas = config
+                .windows
+                .intellihide
+                .then(|| {
+                    let name = output.as_deref().or(outputs.focused.as_deref())?;
+                    let monitors = monitors.read().unwrap();
+                    let monitor = monitors.iter().find(|m| m.name == name)?;
+                    Some(bar.areas(monitor, &config.cursor))
+                })
+                .flatten();
+            let Decision { visible, reason } = bar.engine.decide(&Inputs {
+                at_edge,
+                output: output.as_deref(),
+                outputs: &outputs,
+                pinned,
+                peeking: peek_until.is_some() || bar.peek_until.is_some(),
+                bar_areas: bar_areas.as_deref(),
+            });
+
+            let changed = (visible, reason) != (bar.visible, bar.reason);
+            bar.reason = reason;
+            bar.apply(visible, &config.waybar);
+            if changed {
+                let line = transition(bar);
+                subscribers.retain(|subscriber| subscriber.send(line.clone()).is_ok());
+            }
+        }
+        // Reloads and newly read Waybar configs can move bars to other edges
+        let current = zones(&bars, &config.cursor);
+        if *bar_zones.read().unwrap() != current {
+            *bar_zones.write().unwrap() = current;
+        }
+    }
+    Ok(())
+}
+
+/// The zones of every bar, each one only once
+fn zones(bars: &[Bar], thresholds: &CursorConfig) -> Vec<Zone> {
+    let mut zones: Vec<Zone> = Vec::new();
+    for zone in bars.iter().flat_map(|bar| bar.zones(thresholds)) {
+        if !zones.contains(&zone) {
+            zones.push(zone);
+        }
+    }
+    zones
+}
+
+/// Warns about Waybar configs that do not react to our signals the way we expect
+fn lint_bars(bars: &[Bar], waybar: &WaybarConfig) {
+    for bar in bars {
+        let name = bar.config.name.as_deref().unwrap_or("waybar");
+        if bar.mode == SignalMode::Toggle && waybar.signal_mode == SignalMode::Auto {
+            eprintln!(
+                "waybar_auto_hide: {name}: no show/hide actions in the Waybar config, toggling with {} instead",
+                waybar.hide_signal
+            );
+        }
+        for finding in waybar::lint(&bar.detected, waybar, bar.mode) {
+            let severity = if finding.fatal { "error" } else { "warning" };
+            eprintln!(
+                "waybar_auto_hide: {name}: {severity}: {} ({})",
+                finding.problem, finding.fix
+            );
+        }
+    }
+}
+
+/// A line sent to `subscribe` clients
+fn transition(bar: &Bar) -> String {
+    #[derive(Serialize)]
+    struct Transition<'a> {
+        bar: Option<&'a str>,
+        output: Option<&'a str>,
+        visible: bool,
+        reason: Reason,
+    }
+    serde_json::to_string(&Transition {
+        bar: bar.config.name.as_deref(),
+        output: bar.output(),
+        visible: bar.visible,
+        reason: bar.reason,
+    })
+    .unwrap_or_default()
+}
+
+/// Reply to the `status` control command
+#[derive(Serialize)]
+struct Status<'a> {
+    compositor: &'static str,
+    cursor: &'a CursorState,
+    monitors: Vec<MonitorStatus>,
+    outputs: &'a Outputs,
+    pinned: bool,
+    peeking: bool,
+    bars: Vec<BarStatus<'a>>,
+}
+
+#[derive(Serialize)]
+struct BarStatus<'a> {
+    name: Option<&'a str>,
+    output: Option<&'a str>,
+    visible: bool,
+    reason: Reason,
+    forced: Option<bool>,
+    mode: SignalMode,
+    pids: &'a [i32],
+    zones: Vec<Zone>,
+}
+
+impl<'a> BarStatus<'a> {
+    fn new(bar: &'a Bar, thresholds: &CursorConfig) -> Self {
+        BarStatus {
+            name: bar.config.name.as_deref(),
+            output: bar.output(),
+            visible: bar.visible,
+            reason: bar.reason,
+            forced: bar.engine.forced(),
+            mode: bar.mode,
+            pids: &bar.pids,
+            zones: bar.zones(thresholds),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MonitorStatus {
+    name: String,
+    /// Logical bounds, after scaling and rotation
+    #[serde(flatten)]
+    bounds: Rect,
+    scale: f64,
+    transform: u8,
+    reserved: Reserved,
+}
+
+/// Space taken by exclusive layers along each edge
+#[derive(Serialize)]
+struct Reserved {
+    top: i32,
+    bottom: i32,
+    left: i32,
+    right: i32,
+}
+
+impl From<&Monitor> for MonitorStatus {
+    fn from(monitor: &Monitor) -> Self {
+        MonitorStatus {
+            name: monitor.name.clone(),
+            bounds: monitor.bounds(),
+            scale: monitor.scale,
+            transform: monitor.transform,
+            reserved: Reserved {
+                top: monitor.reserved(Edge::Top),
+                bottom: monitor.reserved(Edge::Bottom),
+                left: monitor.reserved(Edge::Left),
+                right: monitor.reserved(Edge::Right),
+            },
+        }
+    }
+}
+
+/// Where the cursor is, relative to the monitor it is on
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct CursorState {
+    /// The monitor under the cursor
+    pub output: Option<String>,
+    /// Logical pixels from the left edge of that monitor
+    pub x: i32,
+    /// Logical pixels from the top edge of that monitor
+    pub y: i32,
+    /// The logical size of that monitor
+    pub width: i32,
+    /// See `width`
+    pub height: i32,
+}
+
+impl CursorState {
+    /// The cursor at `x`, `y` from the top left corner of a monitor
+    pub fn on(monitor: &Monitor, x: i32, y: i32) -> CursorState {
+        let bounds = monitor.bounds();
+        CursorState {
+            output: Some(monitor.name.clone()),
+            x,
+            y,
+            width: bounds.width,
+            height: bounds.height,
+        }
+    }
+
+    /// How far the cursor is from an edge of its monitor, 0 on the outermost pixel
+    pub fn distance_to(&self, edge: Edge) -> i32 {
+        match edge {
+            Edge::Top => self.y,
+            Edge::Bottom => self.height - 1 - self.y,
+            Edge::Left => self.x,
+            Edge::Right => self.width - 1 - self.x,
+        }
+    }
+}
+
+/// Which monitors show a workspace with windows on it
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct Outputs {
+    /// The monitor with the keyboard focus
+    pub focused: Option<String>,
+    /// Monitor name to whether its active (or open special) workspace has windows
+    pub windows: HashMap<String, bool>,
+    /// Monitor name to the areas covered by the windows it shows, for compositors that report
+    /// window geometry. Monitors missing here are only judged by `windows`.
+    pub areas: HashMap<String, Vec<Rect>>,
+}
+
+/// Keeps track of the mouse position.
+/// Compositors do not send pointer motion events, so the position is polled: quickly while the cursor
+/// moves or is close to the edge of a bar, slowly while it rests elsewhere.
+fn spawn_mouse_position_updated(
+    tx: Sender<Event>,
+    compositor: Arc<dyn Compositor>,
+    cursor_config: Arc<RwLock<CursorConfig>>,
+    bar_zones: ZoneLayout,
+    monitors: MonitorLayout,
+) {
+    thread::spawn(move || {
+        let mut previous_state = CursorState::default();
+        loop {
+            let cursor = cursor_config.read().unwrap().clone();
+            let mut interval = cursor.idle_poll_interval_ms.max(cursor.poll_interval_ms);
+            // The compositor may not have been reachable when the layout was first queried
+            if monitors.read().unwrap().is_empty()
+                && let Some(fresh) = compositor.monitors()
+            {
+                *monitors.write().unwrap() = fresh;
+            }
+            if let Some(pos) = compositor.cursor_pos() {
+                let monitors = monitors.read().unwrap();
+                // Multi-monitor fix: Find which monitor the cursor is currently on
+                if let Some(m) = monitor::at(&monitors, pos.x, pos.y) {
+                    let bounds = m.bounds();
+                    let state = CursorState::on(m, pos.x - bounds.x, pos.y - bounds.y);
+
+                    let near_edge = bar_zones.read().unwrap().iter().any(|zone| {
+                        zone.shown_on(&m.name)
+                            && state.distance_to(zone.edge) <= cursor.near_distance
+                    });
+                    if near_edge || state != previous_state {
+                        interval = cursor.poll_interval_ms;
+                    }
+
+                    // Which bars this reveals is up to the event loop, it knows their edges
+                    if state != previous_state {
+                        tx.send(Event::Cursor(state.clone())).ok();
+                    }
+                    previous_state = state;
+                }
+            }
+            thread::sleep(Duration::from_millis(interval));
+        }
+    });
+}
+
+/// What wakes up the event loop of the daemon
+#[derive(Debug)]
+pub enum Event {
+    /// The cursor moved, or was polled
+    Cursor(CursorState),
+    /// Which monitors show windows changed
+    Windows(Outputs),
+    /// The focused workspace or monitor changed, on the given monitor when it is known
+    WorkspaceSwitched(Option<String>),
+    /// The config file was changed and read again
+    ConfigReloaded(Box<Config>),
+    /// A control command, and where to send the reply
+    Control(ControlCommand, Sender<String>),
+}
+
+/// The monitors as last reported by the compositor, shared by the threads that need their geometry
+pub type MonitorLayout = Arc<RwLock<Vec<Monitor>>>;
+
+/// The zones of every bar, shared with the threads following the cursor
+pub type ZoneLayout = Arc<RwLock<Vec<Zone>>>;
@@ -0,0 +1,78 @@
+use crate::{
+    bar::find_waybar_processes,
+    config::{Config, SignalMode},
+    waybar::{self, Finding},
+};
+use std::path::PathBuf;
+
+/// Checks the Waybar config of every running instance, or the default one when Waybar is not running,
+/// and prints what to change. Returns false if a bar cannot be driven at all.
+pub fn run(config: &Config) -> bool {
+    let waybar = &config.waybar;
+
+    // Instances sharing a config file are reported together
+    let mut configs: Vec<(PathBuf, Vec<i32>)> = Vec::new();
+    for process in find_waybar_processes(waybar) {
+        let Some(path) = waybar::locate_config(process.pid, process.config.as_deref()) else {
+            println!("waybar (pid {}): no config file found", process.pid);
+            continue;
+        };
+        match configs.iter_mut().find(|(p, _)| *p == path) {
+            Some((_, pids)) => pids.push(process.pid),
+            None => configs.push((path, vec![process.pid])),
+        }
+    }
+    if configs.is_empty() {
+        match waybar::locate_config(0, None) {
+            Some(path) => {
+                println!("Waybar is not running, checking its default config");
+                configs.push((path, Vec::new()));
+            }
+            None => {
+                println!("Waybar is not running and has no config file");
+                return true;
+            }
+        }
+    }
+
+    let mut ok = true;
+    for (path, pids) in configs {
+        let pids: Vec<String> = pids.iter().map(i32::to_string).collect();
+        match pids.as_slice() {
+            [] => println!("{}", path.display()),
+            _ => println!("{} (pid {})", path.display(), pids.join(", ")),
+        }
+        let Some(bars) = waybar::read_config(&path) else {
+            println!("  could not be read");
+            ok = false;
+            continue;
+        };
+        let mode = waybar::signal_mode(&bars, waybar);
+        if mode == SignalMode::Toggle && waybar.signal_mode == SignalMode::Auto {
+            println!(
+                "  no show/hide actions, the daemon toggles the bar with {} and tracks its state",
+                waybar.hide_signal
+            );
+        }
+        let findings = waybar::lint(&bars, waybar, mode);
+        if findings.is_empty() {
+            println!("  ok");
+        }
+        for finding in findings {
+            ok &= !finding.fatal;
+            print_finding(&finding, bars.len());
+        }
+    }
+    ok
+}
+
+fn print_finding(finding: &Finding, bars: usize) {
+    let severity = if finding.fatal { "error" } else { "warning" };
+    let bar = if bars > 1 {
+        format!("bar {}: ", finding.bar + 1)
+    } else {
+        String::new()
+    };
+    println!("  {severity}: {bar}{}", finding.problem);
+    println!("    fix: {}", finding.fix);
+}
@@ -0,0 +1,769 @@
+use crate::{
+    CursorState, Event, MonitorLayout,
+    bar::Zone,
+    compositor,
+    config::Edge,
+    daemon::ZoneLayout,
+    monitor::Monitor,
+    wayland::{self, Arg, Args, Connection, DISPLAY, Message, invalid},
+};
+use std::{
+    collections::HashMap,
+    io,
+    os::{fd::AsRawFd, unix::net::UnixStream},
+    path::Path,
+    sync::mpsc::Sender,
+    time::Duration,
+};
+
+/// How often the zones and the monitors are checked for changes while the pointer stays away
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `zwlr_layer_shell_v1.layer.top`, where Waybar is too
+const LAYER_TOP: u32 = 2;
+
+/// `zwlr_layer_surface_v1.anchor` bits
+const ANCHOR_TOP: u32 = 1;
+const ANCHOR_BOTTOM: u32 = 2;
+const ANCHOR_LEFT: u32 = 4;
+const ANCHOR_RIGHT: u32 = 8;
+
+/// The globals without which no surface can be shown
+const REQUIRED: [&str; 4] = [
+    "wl_compositor",
+    "wl_shm",
+    "wp_viewporter",
+    "zwlr_layer_shell_v1",
+];
+
+/// Follows the cursor over transparent layer-shell surfaces, for compositors that do not tell
+/// where it is. Wayland clients only see the pointer over their own surfaces, so:
+///
+/// - a strip as deep as the reveal threshold lies along every edge a bar is on;
+/// - once the pointer enters one, the strips make way for a surface covering everything past the
+///   hide threshold of that edge, and one over every other monitor;
+/// - the first of those the pointer enters tells where it went, and the strips come back.
+///
+/// In between, the cursor is taken to be where it entered the strip. Strips go away as soon as
+/// the pointer enters them, so they do not keep the clicks on the edge of a shown bar from Waybar.
+pub fn follow(tx: Sender<Event>, zones: ZoneLayout, monitors: MonitorLayout) {
+    let Some(socket) = wayland::socket() else {
+        eprintln!(
+            "waybar_auto_hide: WAYLAND_DISPLAY is not set, bars are not revealed at the screen edges"
+        );
+        return;
+    };
+    if let Some(Some(missing)) = HotZones::connect(&socket).map(|hot| hot.missing()) {
+        eprintln!(
+            "waybar_auto_hide: the compositor does not support {missing}, bars are not revealed at the screen edges"
+        );
+        return;
+    }
+    compositor::reconnecting(
+        "the Wayland display",
+        || HotZones::connect(&socket),
+        |hot| hot.follow(&tx, &zones, &monitors),
+    );
+}
+
+/// What the proxy behind an id is
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Object {
+    Registry,
+    Callback,
+    Output,
+    Seat,
+    Pointer,
+    /// The `zwlr_layer_surface_v1` of a `wl_surface`
+    LayerSurface(u32),
+}
+
+#[derive(Debug, Default)]
+struct Output {
+    /// From `wl_output` version 4
+    name: String,
+    /// Position in the layout, to find the monitor of unnamed outputs
+    x: i32,
+    y: i32,
+}
+
+/// Where a surface goes on a monitor
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Placement {
+    anchor: u32,
+    /// 0 along the anchored sides, which the surface stretches to
+    width: i32,
+    height: i32,
+    /// Top, right, bottom and left
+    margin: [i32; 4],
+    /// The top left corner of the surface on the monitor
+    x: i32,
+    y: i32,
+    /// The edge of a strip, `None` for the surfaces telling the pointer left
+    strip: Option<Edge>,
+}
+
+impl Placement {
+    /// Along an edge, as deep as the deepest reveal threshold of the zones there
+    fn strip(monitor: &Monitor, edge: Edge, depth: i32) -> Placement {
+        let bounds = monitor.bounds();
+        let (sides, width, height, x, y) = match edge {
+            Edge::Top => (ANCHOR_LEFT | ANCHOR_RIGHT, 0, depth, 0, 0),
+            Edge::Bottom => (
+                ANCHOR_LEFT | ANCHOR_RIGHT,
+                0,
+                depth,
+                0,
+                bounds.height - depth,
+            ),
+            Edge::Left => (ANCHOR_TOP | ANCHOR_BOTTOM, depth, 0, 0, 0),
+            Edge::Right => (
+                ANCHOR_TOP | ANCHOR_BOTTOM,
+                depth,
+                0,
+                bounds.width - depth,
+                0,
+            ),
+        };
+        Placement {
+            anchor: sides | anchor(edge),
+            width,
+            height,
+            margin: [0; 4],
+            x,
+            y,
+            strip: Some(edge),
+        }
+    }
+
+    /// The whole monitor but a band along `edge`
+    fn exit(monitor: &Monitor, band: Option<(Edge, i32)>) -> Placement {
+        let bounds = monitor.bounds();
+        let mut placement = Placement {
+            anchor: ANCHOR_TOP | ANCHOR_BOTTOM | ANCHOR_LEFT | ANCHOR_RIGHT,
+            width: 0,
+            height: 0,
+            margin: [0; 4],
+            x: 0,
+            y: 0,
+            strip: None,
+        };
+        if let Some((edge, depth)) = band {
+            // Some of the monitor has to be left for the surface
+            let depth = match edge {
+                Edge::Top | Edge::Bottom => depth.min(bounds.height - 1),
+                Edge::Left | Edge::Right => depth.min(bounds.width - 1),
+            };
+            match edge {
+                Edge::Top => (placement.margin[0], placement.y) = (depth, depth),
+                Edge::Right => placement.margin[1] = depth,
+                Edge::Bottom => placement.margin[2] = depth,
+                Edge::Left => (placement.margin[3], placement.x) = (depth, depth),
+            }
+        }
+        placement
+    }
+}
+
+fn anchor(edge: Edge) -> u32 {
+    match edge {
+        Edge::Top => ANCHOR_TOP,
+        Edge::Bottom => ANCHOR_BOTTOM,
+        Edge::Left => ANCHOR_LEFT,
+        Edge::Right => ANCHOR_RIGHT,
+    }
+}
+
+/// The surfaces a monitor gets. With the pointer in the band of a bar, `band` tells on which
+/// monitor and along which edge.
+fn placements(zones: &[Zone], monitor: &Monitor, band: Option<&(String, Edge)>) -> Vec<Placement> {
+    let deepest = |edge: Edge, depth: fn(&Zone) -> i32| {
+        zones
+            .iter()
+            .filter(|zone| zone.edge == edge && zone.shown_on(&monitor.name))
+            .map(depth)
+            .max()
+    };
+    match band {
+        // The cursor is within the threshold on the outermost pixel too, hence the + 1
+        None => [Edge::Top, Edge::Bottom, Edge::Left, Edge::Right]
+            .into_iter()
+            .filter_map(|edge| {
+                let depth = deepest(edge, |zone| zone.reveal + 1)?;
+                Some(Placement::strip(monitor, edge, depth))
+            })
+            .collect(),
+        Some((output, edge)) if *output == monitor.name => {
+            let depth = deepest(*edge, |zone| zone.hide + 1).unwrap_or(0);
+            vec![Placement::exit(monitor, Some((*edge, depth)))]
+        }
+        Some(_) => vec![Placement::exit(monitor, None)],
+    }
+}
+
+/// A surface of ours, shown on a monitor
+#[derive(Debug)]
+struct Surface {
+    layer: u32,
+    viewport: u32,
+    monitor: Monitor,
+    placement: Placement,
+}
+
+/// The connection and the surfaces laid out on it
+struct HotZones {
+    conn: Connection,
+    objects: HashMap<u32, Object>,
+    /// Interface to id of the globals surfaces are made from
+    globals: HashMap<&'static str, u32>,
+    /// Registry name of every bound `wl_output`, to notice when it goes away
+    output_globals: HashMap<u32, u32>,
+    outputs: HashMap<u32, Output>,
+    /// A transparent pixel, stretched over every surface
+    buffer: Option<u32>,
+    surfaces: HashMap<u32, Surface>,
+    /// The monitor and edge of the band the pointer is in, `None` while it is away from the bars
+    band: Option<(String, Edge)>,
+    /// What the surfaces were laid out for
+    zones: Vec<Zone>,
+    monitors: Vec<Monitor>,
+    /// Whether the outputs changed since the surfaces were laid out
+    stale: bool,
+}
+
+impl HotZones {
+    fn new(stream: UnixStream) -> io::Result<HotZones> {
+        Ok(HotZones {
+            conn: Connection::new(stream)?,
+            objects: HashMap::new(),
+            globals: HashMap::new(),
+            output_globals: HashMap::new(),
+            outputs: HashMap::new(),
+            buffer: None,
+            surfaces: HashMap::new(),
+            band: None,
+            zones: Vec::new(),
+            monitors: Vec::new(),
+            stale: true,
+        })
+    }
+
+    fn connect(socket: &Path) -> Option<HotZones> {
+        let mut hot = HotZones::new(UnixStream::connect(socket).ok()?).ok()?;
+        hot.start().ok()?;
+        Some(hot)
+    }
+
+    /// The first global missing to show surfaces
+    fn missing(&self) -> Option<&'static str> {
+        REQUIRED
+            .into_iter()
+            .find(|interface| !self.globals.contains_key(interface))
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        let registry = self.conn.new_id();
+        self.conn.send(DISPLAY, 1, &[Arg::Id(registry)])?;
+        self.objects.insert(registry, Object::Registry);
+        // Globals, then the names of the outputs and the capabilities of the seats
+        for _ in 0..2 {
+            self.roundtrip()?;
+        }
+        if self.missing().is_none() {
+            self.create_buffer()?;
+        }
+        Ok(())
+    }
+
+    /// Handles events until the compositor has processed every request sent so far
+    fn roundtrip(&mut self) -> io::Result<()> {
+        let callback = self.conn.new_id();
+        self.conn.send(DISPLAY, 0, &[Arg::Id(callback)])?;
+        self.objects.insert(callback, Object::Callback);
+        loop {
+            let message = self.conn.read()?;
+            if message.object == callback {
+                self.objects.remove(&callback);
+                return Ok(());
+            }
+            self.dispatch(message)?;
+        }
+    }
+
+    /// A single transparent pixel, which the viewports scale to the size of each surface
+    fn create_buffer(&mut self) -> io::Result<()> {
+        let fd = unsafe { libc::memfd_create(c"waybar_auto_hide".as_ptr(), libc::MFD_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // Owning it closes it once the compositor has its own copy
+        let file = unsafe { <std::fs::File as std::os::fd::FromRawFd>::from_raw_fd(fd) };
+        file.set_len(4)?;
+        let pool = self.conn.new_id();
+        self.conn.send_fd(
+            self.globals["wl_shm"],
+            0,
+            &[Arg::Id(pool), Arg::Int(4)],
+            file.as_raw_fd(),
+        )?;
+        let buffer = self.conn.new_id();
+        // Offset, width, height, stride and ARGB8888, in which all zeros is fully transparent
+        self.conn.send(
+            pool,
+            0,
+            &[
+                Arg::Id(buffer),
+                Arg::Int(0),
+                Arg::Int(1),
+                Arg::Int(1),
+                Arg::Int(4),
+                Arg::Uint(0),
+            ],
+        )?;
+        self.conn.send(pool, 1, &[])?;
+        self.buffer = Some(buffer);
+        Ok(())
+    }
+
+    fn bind(
+        &mut self,
+        name: u32,
+        interface: &str,
+        version: u32,
+        kind: Option<Object>,
+    ) -> io::Result<u32> {
+        let id = self.conn.new_id();
+        let registry = self
+            .objects
+            .iter()
+            .find(|(_, kind)| **kind == Object::Registry)
+            .map(|(id, _)| *id)
+            .ok_or_else(|| invalid("no registry"))?;
+        self.conn.send(
+            registry,
+            0,
+            &[
+                Arg::Uint(name),
+                Arg::Str(interface),
+                Arg::Uint(version),
+                Arg::Id(id),
+            ],
+        )?;
+        if let Some(kind) = kind {
+            self.objects.insert(id, kind);
+        }
+        Ok(id)
+    }
+
+    /// Keeps the surfaces in place and tells where the pointer went until the connection closes.
+    /// Returns false once the event loop is gone.
+    fn follow(mut self, tx: &Sender<Event>, zones: &ZoneLayout, monitors: &MonitorLayout) -> bool {
+        loop {
+            let (zones, monitors) = (
+                zones.read().unwrap().clone(),
+                monitors.read().unwrap().clone(),
+            );
+            if (self.stale || zones != self.zones || monitors != self.monitors)
+                && self.lay_out(zones, monitors).is_err()
+            {
+                return true;
+            }
+            match self.conn.wait(CHECK_INTERVAL) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(_) => return true,
+            }
+            let Ok(message) = self.conn.read() else {
+                return true;
+            };
+            match self.dispatch(message) {
+                Ok(Some(cursor)) => {
+                    if tx.send(Event::Cursor(cursor)).is_err() {
+                        return false;
+                    }
+                }
+                Ok(None) => {}
+                Err(_) => return true,
+            }
+        }
+    }
+
+    /// Replaces every surface with the ones the monitors get now
+    fn lay_out(&mut self, zones: Vec<Zone>, monitors: Vec<Monitor>) -> io::Result<()> {
+        (self.zones, self.monitors, self.stale) = (zones, monitors, false);
+        let surfaces: Vec<u32> = self.surfaces.keys().copied().collect();
+        for surface in surfaces {
+            self.destroy(surface)?;
+        }
+        let mut outputs: Vec<(u32, Monitor)> = self
+            .outputs
+            .iter()
+            .filter_map(|(&id, output)| {
+                let monitor = self.monitors.iter().find(|m| {
+                    if output.name.is_empty() {
+                        (m.x, m.y) == (output.x, output.y)
+                    } else {
+                        m.name == output.name
+                    }
+                })?;
+                Some((id, monitor.clone()))
+            })
+            .collect();
+        outputs.sort_by_key(|(id, _)| *id);
+        for (output, monitor) in outputs {
+            for placement in placements(&self.zones, &monitor, self.band.as_ref()) {
+                self.create(output, &monitor, placement)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a layer surface, mapped once the compositor has configured it
+    fn create(&mut self, output: u32, monitor: &Monitor, placement: Placement) -> io::Result<()> {
+        let (compositor, layer_shell, viewporter) = match (
+            self.globals.get("wl_compositor"),
+            self.globals.get("zwlr_layer_shell_v1"),
+            self.globals.get("wp_viewporter"),
+        ) {
+            (Some(&c), Some(&l), Some(&v)) => (c, l, v),
+            _ => return Ok(()),
+        };
+        let surface = self.conn.new_id();
+        self.conn.send(compositor, 0, &[Arg::Id(surface)])?;
+        let layer = self.conn.new_id();
+        self.conn.send(
+            layer_shell,
+            0,
+            &[
+                Arg::Id(layer),
+                Arg::Id(surface),
+                Arg::Id(output),
+                Arg::Uint(LAYER_TOP),
+                Arg::Str("waybar_auto_hide"),
+            ],
+        )?;
+        let [top, right, bottom, left] = placement.margin;
+        self.conn.send(
+            layer,
+            0,
+            &[
+                Arg::Uint(placement.width as u32),
+                Arg::Uint(placement.height as u32),
+            ],
+        )?;
+        self.conn.send(layer, 1, &[Arg::Uint(placement.anchor)])?;
+        // Measured from the edge of the monitor, wherever Waybar reserves space
+        self.conn.send(layer, 2, &[Arg::Int(-1)])?;
+        self.conn.send(
+            layer,
+            3,
+            &[
+                Arg::Int(top),
+                Arg::Int(right),
+                Arg::Int(bottom),
+                Arg::Int(left),
+            ],
+        )?;
+        let viewport = self.conn.new_id();
+        self.conn
+            .send(viewporter, 1, &[Arg::Id(viewport), Arg::Id(surface)])?;
+        self.conn.send(surface, 6, &[])?;
+        self.objects.insert(layer, Object::LayerSurface(surface));
+        self.surfaces.insert(
+            surface,
+            Surface {
+                layer,
+                viewport,
+                monitor: monitor.clone(),
+                placement,
+            },
+        );
+        Ok(())
+    }
+
+    fn destroy(&mut self, surface: u32) -> io::Result<()> {
+        let Some(Surface {
+            layer, viewport, ..
+        }) = self.surfaces.remove(&surface)
+        else {
+            return Ok(());
+        };
+        self.objects.remove(&layer);
+        self.conn.send(viewport, 0, &[])?;
+        self.conn.send(layer, 7, &[])?;
+        self.conn.send(surface, 0, &[])
+    }
+
+    /// Handles an event, returning where the cursor is when the pointer entered a surface
+    fn dispatch(&mut self, message: Message) -> io::Result<Option<CursorState>> {
+        let mut args = Args(&message.body);
+        if message.object == DISPLAY {
+            return match message.opcode {
+                0 => {
+                    let (_, _) = (args.uint()?, args.uint()?);
+                    Err(io::Error::other(format!(
+                        "Wayland error: {}",
+                        args.string()?
+                    )))
+                }
+                1 => {
+                    self.objects.remove(&args.uint()?);
+                    Ok(None)
+                }
+                _ => Ok(None),
+            };
+        }
+        let Some(&kind) = self.objects.get(&message.object) else {
+            return Ok(None);
+        };
+        let id = message.object;
+        match (kind, message.opcode) {
+            (Object::Registry, 0) => {
+                let (name, interface, version) = (args.uint()?, args.string()?, args.uint()?);
+                match interface.as_str() {
+                    "wl_output" => {
+                        // Version 4 adds the output name
+                        let output =
+                            self.bind(name, &interface, version.min(4), Some(Object::Output))?;
+                        self.outputs.insert(output, Output::default());
+                        self.output_globals.insert(name, output);
+                        self.stale = true;
+                    }
+                    "wl_seat" => {
+                        self.bind(name, &interface, 1, Some(Object::Seat))?;
+                    }
+                    interface => {
+                        if let Some(&required) = REQUIRED.iter().find(|&&r| r == interface) {
+                            let global = self.bind(name, required, 1, None)?;
+                            self.globals.insert(required, global);
+                        }
+                    }
+                }
+            }
+            (Object::Registry, 1) => {
+                if let Some(output) = self.output_globals.remove(&args.uint()?) {
+                    self.outputs.remove(&output);
+                    self.objects.remove(&output);
+                    self.stale = true;
+                }
+            }
+            (Object::Output, 0) => {
+                let output = self.outputs.entry(id).or_default();
+                (output.x, output.y) = (args.int()?, args.int()?);
+            }
+            (Object::Output, 4) => {
+                self.outputs.entry(id).or_default().name = args.string()?;
+                self.stale = true;
+            }
+            // Capabilities, the pointer is the first bit
+            (Object::Seat, 0) => {
+                let has_pointer = self.objects.values().any(|&kind| kind == Object::Pointer);
+                if args.uint()? & 1 != 0 && !has_pointer {
+                    let pointer = self.conn.new_id();
+                    self.conn.send(id, 0, &[Arg::Id(pointer)])?;
+                    self.objects.insert(pointer, Object::Pointer);
+                }
+            }
+            (Object::LayerSurface(surface), 0) => {
+                let (serial, width, height) = (args.uint()?, args.int()?, args.int()?);
+                self.conn.send(id, 6, &[Arg::Uint(serial)])?;
+                let (Some(buffer), Some(viewport)) =
+                    (self.buffer, self.surfaces.get(&surface).map(|s| s.viewport))
+                else {
+                    return Ok(None);
+                };
+                // A zero size would be a protocol error, such a surface is left unmapped
+                if width > 0 && height > 0 {
+                    self.conn
+                        .send(viewport, 2, &[Arg::Int(width), Arg::Int(height)])?;
+                    self.conn
+                        .send(surface, 1, &[Arg::Id(buffer), Arg::Int(0), Arg::Int(0)])?;
+                    self.conn.send(surface, 6, &[])?;
+                }
+            }
+            // Closed, its output is most likely gone
+            (Object::LayerSurface(surface), 1) => self.destroy(surface)?,
+            // Enter, with the position in 24.8 fixed point
+            (Object::Pointer, 0) => {
+                let (_serial, surface) = (args.uint()?, args.uint()?);
+                let (x, y) = (args.int()? >> 8, args.int()? >> 8);
+                return self.entered(surface, x, y);
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// The pointer reached a strip or left the band of a bar, so the other kind of surfaces
+    /// takes over
+    fn entered(&mut self, surface: u32, x: i32, y: i32) -> io::Result<Option<CursorState>> {
+        let Some(entered) = self.surfaces.get(&surface) else {
+            return Ok(None);
+        };
+        let (monitor, placement) = (&entered.monitor, entered.placement);
+        let cursor = CursorState::on(monitor, placement.x + x, placement.y + y);
+        self.band = placement.strip.map(|edge| (monitor.name.clone(), edge));
+        let (zones, monitors) = (
+            std::mem::take(&mut self.zones),
+            std::mem::take(&mut self.monitors),
+        );
+        self.lay_out(zones, monitors)?;
+        Ok(Some(cursor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(name: &str, x: i32) -> Monitor {
+        Monitor::logical(name.to_string(), x, 0, 1920, 1080)
+    }
+
+    fn zone(edge: Edge, reveal: i32, hide: i32, outputs: Option<&[&str]>) -> Zone {
+        Zone {
+            edge,
+            reveal,
+            hide,
+            outputs: outputs.map(|o| o.iter().map(|s| s.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn strips_follow_the_deepest_zone_of_each_edge() {
+        let zones = [
+            zone(Edge::Top, 3, 50, None),
+            zone(Edge::Top, 5, 30, Some(&["DP-1"])),
+            zone(Edge::Right, 2, 40, Some(&["DP-2"])),
+        ];
+        let strips = placements(&zones, &monitor("DP-1", 0), None);
+        assert_eq!(strips.len(), 1);
+        assert_eq!(strips[0].strip, Some(Edge::Top));
+        assert_eq!((strips[0].width, strips[0].height), (0, 6));
+        assert_eq!(strips[0].anchor, ANCHOR_TOP | ANCHOR_LEFT | ANCHOR_RIGHT);
+
+        let strips = placements(&zones, &monitor("DP-2", 1920), None);
+        let right = strips
+            .iter()
+            .find(|p| p.strip == Some(Edge::Right))
+            .unwrap();
+        assert_eq!((right.width, right.height), (3, 0));
+        assert_eq!((right.x, right.y), (1917, 0));
+    }
+
+    #[test]
+    fn the_band_is_left_uncovered_on_its_monitor_only() {
+        let zones = [zone(Edge::Bottom, 3, 50, None)];
+        let band = ("DP-1".to_string(), Edge::Bottom);
+        let exits = placements(&zones, &monitor("DP-1", 0), Some(&band));
+        assert_eq!(exits.len(), 1);
+        assert_eq!(exits[0].strip, None);
+        assert_eq!(exits[0].margin, [0, 0, 51, 0]);
+        let exits = placements(&zones, &monitor("DP-2", 1920), Some(&band));
+        assert_eq!(exits[0].margin, [0; 4]);
+
+        // A band deeper than the monitor still leaves some of it
+        let zones = [zone(Edge::Top, 3, 5000, None)];
+        let band = ("DP-1".to_string(), Edge::Top);
+        let exits = placements(&zones, &monitor("DP-1", 0), Some(&band));
+        assert_eq!(exits[0].margin, [1079, 0, 0, 0]);
+        assert_eq!(exits[0].y, 1079);
+    }
+
+    /// Encodes an event the way the compositor would, by sending it as a request
+    fn event(object: u32, opcode: u16, args: &[Arg]) -> Message {
+        let (a, b) = UnixStream::pair().unwrap();
+        let mut writer = Connection::new(a).unwrap();
+        writer.send(object, opcode, args).unwrap();
+        Connection::new(b).unwrap().read().unwrap()
+    }
+
+    /// The surface laid out as a strip along an edge, or as an exit with `None`
+    fn surface_at(hot: &HotZones, strip: Option<Edge>) -> u32 {
+        hot.surfaces
+            .iter()
+            .find(|(_, s)| s.placement.strip == strip)
+            .map(|(id, _)| *id)
+            .unwrap()
+    }
+
+    #[test]
+    fn entering_a_strip_uncovers_the_band_until_the_pointer_leaves_it() {
+        let (client, _server) = UnixStream::pair().unwrap();
+        let mut hot = HotZones::new(client).unwrap();
+        let registry = hot.conn.new_id();
+        hot.objects.insert(registry, Object::Registry);
+        let dispatch = |hot: &mut HotZones, object, opcode, args: &[Arg]| {
+            hot.dispatch(event(object, opcode, args)).unwrap()
+        };
+        for (name, interface) in [
+            (1, "wl_compositor"),
+            (2, "wp_viewporter"),
+            (3, "zwlr_layer_shell_v1"),
+            (4, "wl_seat"),
+            (5, "wl_output"),
+        ] {
+            dispatch(
+                &mut hot,
+                registry,
+                0,
+                &[Arg::Uint(name), Arg::Str(interface), Arg::Uint(4)],
+            );
+        }
+        let output = hot.output_globals[&5];
+        dispatch(&mut hot, output, 4, &[Arg::Str("DP-1")]);
+        let seat = hot
+            .objects
+            .iter()
+            .find(|(_, k)| **k == Object::Seat)
+            .map(|(id, _)| *id)
+            .unwrap();
+        dispatch(&mut hot, seat, 0, &[Arg::Uint(3)]);
+        let pointer = hot
+            .objects
+            .iter()
+            .find(|(_, k)| **k == Object::Pointer)
+            .map(|(id, _)| *id)
+            .unwrap();
+
+        hot.lay_out(
+            vec![zone(Edge::Bottom, 3, 50, None)],
+            vec![monitor("DP-1", 0)],
+        )
+        .unwrap();
+        let strip = surface_at(&hot, Some(Edge::Bottom));
+        let entered = dispatch(
+            &mut hot,
+            pointer,
+            0,
+            &[
+                Arg::Uint(1),
+                Arg::Id(strip),
+                Arg::Int(100 << 8),
+                Arg::Int(2 << 8),
+            ],
+        );
+        assert_eq!(
+            entered.map(|c| (c.x, c.y, c.distance_to(Edge::Bottom))),
+            Some((100, 1078, 1))
+        );
+        assert_eq!(hot.band, Some(("DP-1".to_string(), Edge::Bottom)));
+        assert_eq!(hot.surfaces.len(), 1);
+
+        let exit = surface_at(&hot, None);
+        let left = dispatch(
+            &mut hot,
+            pointer,
+            0,
+            &[
+                Arg::Uint(2),
+                Arg::Id(exit),
+                Arg::Int(100 << 8),
+                Arg::Int(1020 << 8),
+            ],
+        );
+        assert_eq!(left.map(|c| c.y), Some(1020));
+        assert_eq!(hot.band, None);
+        surface_at(&hot, Some(Edge::Bottom));
+    }
+}
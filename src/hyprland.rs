@@ -0,0 +1,929 @@
+use crate::{
+    Event, MonitorLayout, Outputs,
+    compositor::{self, Compositor, CursorPos, OutputsSender},
+    monitor::{Monitor, Rect, WorkspaceRef},
+};
+use serde::{Deserialize, Deserializer, de::Error};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+    },
+};
+
+/// A window address, as printed in hex by Hyprland
+pub type Address = u64;
+
+/// An event from Hyprland's `.socket2.sock`, for the events the daemon cares about.
+/// Where Hyprland sends both a v1 and a v2 form, only one of them is parsed so nothing is seen twice.
+/// The fields are the arguments of the event, under Hyprland's names.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum HyprEvent {
+    /// `workspacev2`: the focused workspace changed
+    Workspace { id: i64, name: String },
+    /// `focusedmonv2`: the focus moved to another monitor
+    FocusedMonitor { monitor: String, workspace_id: i64 },
+    /// `createworkspacev2`
+    CreateWorkspace { id: i64, name: String },
+    /// `destroyworkspacev2`
+    DestroyWorkspace { id: i64, name: String },
+    /// `moveworkspacev2`: a workspace was moved to another monitor
+    MoveWorkspace {
+        id: i64,
+        name: String,
+        monitor: String,
+    },
+    /// `activespecial`: a special workspace was opened on a monitor, or closed when `name` is empty
+    ActiveSpecial { name: String, monitor: String },
+    /// `openwindow`
+    OpenWindow {
+        address: Address,
+        workspace: String,
+        class: String,
+        title: String,
+    },
+    /// `closewindow`
+    CloseWindow { address: Address },
+    /// `movewindowv2`
+    MoveWindow {
+        address: Address,
+        workspace_id: i64,
+        workspace: String,
+    },
+    /// `activewindowv2`, `None` when no window has the focus
+    ActiveWindow { address: Option<Address> },
+    /// `fullscreen`: the focused window entered or left fullscreen
+    Fullscreen(bool),
+    /// `changefloatingmode`
+    ChangeFloatingMode { address: Address, floating: bool },
+    /// `monitoradded`
+    MonitorAdded { name: String },
+    /// `monitorremoved`
+    MonitorRemoved { name: String },
+    /// `configreloaded`
+    ConfigReloaded,
+}
+
+impl HyprEvent {
+    /// Parses one `EVENT>>DATA` line. Returns `None` for events the daemon does not use
+    /// and for malformed lines.
+    pub fn parse(line: &str) -> Option<HyprEvent> {
+        let (event, data) = line.split_once(">>")?;
+        let event = match event {
+            "workspacev2" => {
+                let (id, name) = data.split_once(',')?;
+                HyprEvent::Workspace {
+                    id: id.parse().ok()?,
+                    name: name.to_string(),
+                }
+            }
+            "focusedmonv2" => {
+                let (monitor, id) = data.rsplit_once(',')?;
+                HyprEvent::FocusedMonitor {
+                    monitor: monitor.to_string(),
+                    workspace_id: id.parse().ok()?,
+                }
+            }
+            "createworkspacev2" | "destroyworkspacev2" => {
+                let (id, name) = data.split_once(',')?;
+                let (id, name) = (id.parse().ok()?, name.to_string());
+                if event == "createworkspacev2" {
+                    HyprEvent::CreateWorkspace { id, name }
+                } else {
+                    HyprEvent::DestroyWorkspace { id, name }
+                }
+            }
+            "moveworkspacev2" => {
+                let (id, rest) = data.split_once(',')?;
+                let (name, monitor) = rest.rsplit_once(',')?;
+                HyprEvent::MoveWorkspace {
+                    id: id.parse().ok()?,
+                    name: name.to_string(),
+                    monitor: monitor.to_string(),
+                }
+            }
+            "activespecial" => {
+                let (name, monitor) = data.rsplit_once(',')?;
+                HyprEvent::ActiveSpecial {
+                    name: name.to_string(),
+                    monitor: monitor.to_string(),
+                }
+            }
+            "openwindow" => {
+                // The title comes last and may contain commas
+                let mut fields = data.splitn(4, ',');
+                HyprEvent::OpenWindow {
+                    address: address(fields.next()?)?,
+                    workspace: fields.next()?.to_string(),
+                    class: fields.next()?.to_string(),
+                    title: fields.next().unwrap_or_default().to_string(),
+                }
+            }
+            "closewindow" => HyprEvent::CloseWindow {
+                address: address(data)?,
+            },
+            "movewindowv2" => {
+                let mut fields = data.splitn(3, ',');
+                HyprEvent::MoveWindow {
+                    address: address(fields.next()?)?,
+                    workspace_id: fields.next()?.parse().ok()?,
+                    workspace: fields.next()?.to_string(),
+                }
+            }
+            "activewindowv2" => HyprEvent::ActiveWindow {
+                address: match data {
+                    "" | "," => None,
+                    data => Some(address(data)?),
+                },
+            },
+            "fullscreen" => HyprEvent::Fullscreen(flag(data)?),
+            "changefloatingmode" => {
+                let (addr, floating) = data.split_once(',')?;
+                HyprEvent::ChangeFloatingMode {
+                    address: address(addr)?,
+                    floating: flag(floating)?,
+                }
+            }
+            "monitoradded" => HyprEvent::MonitorAdded {
+                name: data.to_string(),
+            },
+            "monitorremoved" => HyprEvent::MonitorRemoved {
+                name: data.to_string(),
+            },
+            "configreloaded" => HyprEvent::ConfigReloaded,
+            _ => return None,
+        };
+        Some(event)
+    }
+
+    /// Whether windows may have moved or been resized. Events never tell where windows are,
+    /// only `j/clients` does.
+    pub fn moves_windows(&self) -> bool {
+        matches!(
+            self,
+            HyprEvent::OpenWindow { .. }
+                | HyprEvent::MoveWindow { .. }
+                | HyprEvent::Fullscreen(_)
+                | HyprEvent::ChangeFloatingMode { .. }
+        )
+    }
+
+    /// Whether the monitor layout may have changed
+    pub fn changes_monitors(&self) -> bool {
+        matches!(
+            self,
+            HyprEvent::MonitorAdded { .. }
+                | HyprEvent::MonitorRemoved { .. }
+                | HyprEvent::ConfigReloaded
+        )
+    }
+}
+
+/// Parses an address with or without its `0x` prefix, `j/clients` has one and events do not
+pub fn address(text: &str) -> Option<Address> {
+    let text = text.trim();
+    Address::from_str_radix(text.strip_prefix("0x").unwrap_or(text), 16).ok()
+}
+
+fn flag(text: &str) -> Option<bool> {
+    match text.trim() {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    }
+}
+
+/// A window as reported by `j/clients`
+#[derive(Deserialize, Debug, Clone)]
+pub struct Client {
+    /// Identifies the window in events
+    #[serde(deserialize_with = "deserialize_address")]
+    pub address: Address,
+    /// The workspace it is on
+    pub workspace: WorkspaceRef,
+    /// Unmapped clients are not on screen and have no real workspace
+    #[serde(default = "mapped_by_default")]
+    pub mapped: bool,
+    /// Tiled windows take up the space a hidden bar leaves
+    #[serde(default)]
+    pub floating: bool,
+    /// Position in the layout, like the monitors
+    #[serde(default)]
+    pub at: [i32; 2],
+    /// Width and height
+    #[serde(default)]
+    pub size: [i32; 2],
+}
+
+impl Client {
+    /// Where the window is in the layout
+    pub fn area(&self) -> Rect {
+        Rect {
+            x: self.at[0],
+            y: self.at[1],
+            width: self.size[0],
+            height: self.size[1],
+        }
+    }
+}
+
+fn mapped_by_default() -> bool {
+    true
+}
+
+fn deserialize_address<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Address, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    address(&text).ok_or_else(|| D::Error::custom(format!("invalid window address `{text}`")))
+}
+
+/// A workspace as reported by `j/workspaces`
+#[derive(Deserialize, Debug, Clone)]
+pub struct Workspace {
+    /// Negative for special workspaces
+    pub id: i64,
+    /// The id as a string for unnamed workspaces
+    pub name: String,
+    /// The monitor it lives on
+    #[serde(default)]
+    pub monitor: String,
+}
+
+/// What a monitor currently shows
+#[derive(Debug, Clone, PartialEq)]
+struct Shown {
+    active: i64,
+    /// A special workspace opened on top of the active one
+    special: Option<i64>,
+    bounds: Rect,
+}
+
+/// Which windows are on which workspace, and which workspaces are shown where.
+/// Built once from `j/clients`, `j/monitors` and `j/workspaces`, then kept up to date from events.
+#[derive(Debug, Default)]
+pub struct WindowModel {
+    /// The workspace of every window
+    clients: HashMap<Address, i64>,
+    /// Where the floating windows are. Any other window is tiled, and takes the space
+    /// a hidden bar leaves, so it counts as covering its whole monitor.
+    floating: HashMap<Address, Rect>,
+    /// Workspace id to its name and monitor, since `openwindow` only gives the name
+    workspaces: HashMap<i64, Workspace>,
+    monitors: HashMap<String, Shown>,
+    focused: Option<String>,
+}
+
+impl WindowModel {
+    /// The state at startup, from the replies to `j/clients`, `j/monitors` and `j/workspaces`
+    pub fn new(clients: &[Client], monitors: &[Monitor], workspaces: &[Workspace]) -> WindowModel {
+        WindowModel {
+            clients: clients
+                .iter()
+                .filter(|c| c.mapped)
+                .map(|c| (c.address, c.workspace.id))
+                .collect(),
+            floating: floating(clients),
+            workspaces: workspaces.iter().map(|w| (w.id, w.clone())).collect(),
+            monitors: monitors
+                .iter()
+                .map(|m| {
+                    let special = m.special_workspace.id;
+                    let shown = Shown {
+                        active: m.active_workspace.id,
+                        special: (special != 0).then_some(special),
+                        bounds: m.bounds(),
+                    };
+                    (m.name.clone(), shown)
+                })
+                .collect(),
+            focused: monitors.iter().find(|m| m.focused).map(|m| m.name.clone()),
+        }
+    }
+
+    /// Follows an event. Returns false when the model cannot tell what changed
+    /// and has to be rebuilt from a fresh query.
+    pub fn apply(&mut self, event: &HyprEvent) -> bool {
+        match event {
+            HyprEvent::OpenWindow {
+                address, workspace, ..
+            } => {
+                let Some(id) = self.workspace_id(workspace) else {
+                    return false;
+                };
+                self.clients.insert(*address, id);
+            }
+            HyprEvent::CloseWindow { address } => {
+                self.clients.remove(address);
+                self.floating.remove(address);
+            }
+            HyprEvent::MoveWindow {
+                address,
+                workspace_id,
+                ..
+            } => {
+                self.clients.insert(*address, *workspace_id);
+            }
+            HyprEvent::Workspace { id, .. } => {
+                // Switching to a workspace focuses the monitor it lives on
+                let Some(monitor) = self
+                    .workspaces
+                    .get(id)
+                    .map(|w| w.monitor.clone())
+                    .filter(|m| self.monitors.contains_key(m))
+                else {
+                    return false;
+                };
+                self.show(&monitor, *id);
+                self.focused = Some(monitor);
+            }
+            HyprEvent::FocusedMonitor {
+                monitor,
+                workspace_id,
+            } => {
+                if !self.monitors.contains_key(monitor) {
+                    return false;
+                }
+                self.show(monitor, *workspace_id);
+                self.focused = Some(monitor.clone());
+            }
+            HyprEvent::CreateWorkspace { id, name } => {
+                // New workspaces open on the focused monitor. When a workspace rule puts one
+                // elsewhere, switching to it also sends `focusedmonv2`, which moves it there.
+                let Some(monitor) = self.focused.clone() else {
+                    return false;
+                };
+                self.workspaces.insert(
+                    *id,
+                    Workspace {
+                        id: *id,
+                        name: name.clone(),
+                        monitor,
+                    },
+                );
+            }
+            HyprEvent::DestroyWorkspace { id, .. } => {
+                self.workspaces.remove(id);
+            }
+            HyprEvent::ActiveSpecial { name, monitor } => {
+                let special = match name.as_str() {
+                    "" => None,
+                    name => match self.workspace_id(name) {
+                        Some(id) => Some(id),
+                        None => return false,
+                    },
+                };
+                let Some(shown) = self.monitors.get_mut(monitor) else {
+                    return false;
+                };
+                shown.special = special;
+            }
+            HyprEvent::MoveWorkspace { .. }
+            | HyprEvent::MonitorAdded { .. }
+            | HyprEvent::MonitorRemoved { .. }
+            | HyprEvent::ConfigReloaded => return false,
+            HyprEvent::ChangeFloatingMode {
+                address,
+                floating: false,
+            } => {
+                self.floating.remove(address);
+            }
+            // Covers the whole monitor until `update_geometry` tells where it went
+            HyprEvent::ChangeFloatingMode { .. }
+            | HyprEvent::ActiveWindow { .. }
+            | HyprEvent::Fullscreen(_) => {}
+        }
+        true
+    }
+
+    /// Takes the geometry of the windows from a fresh `j/clients`.
+    /// Windows the model does not know yet are left for the events to add.
+    pub fn update_geometry(&mut self, clients: &[Client]) {
+        self.floating = floating(clients);
+        self.floating
+            .retain(|address, _| self.clients.contains_key(address));
+    }
+
+    /// Which monitors show windows right now, and where
+    pub fn outputs(&self) -> Outputs {
+        let mut outputs = Outputs {
+            focused: self.focused.clone(),
+            ..Default::default()
+        };
+        for (name, shown) in &self.monitors {
+            let areas: Vec<Rect> = self
+                .clients
+                .iter()
+                .filter(|&(_, &ws)| ws == shown.active || Some(ws) == shown.special)
+                .map(|(address, _)| self.floating.get(address).copied().unwrap_or(shown.bounds))
+                .collect();
+            outputs.windows.insert(name.clone(), !areas.is_empty());
+            outputs.areas.insert(name.clone(), areas);
+        }
+        outputs
+    }
+
+    fn show(&mut self, monitor: &str, workspace: i64) {
+        if let Some(shown) = self.monitors.get_mut(monitor) {
+            shown.active = workspace;
+        }
+        if let Some(ws) = self.workspaces.get_mut(&workspace) {
+            ws.monitor = monitor.to_string();
+        }
+    }
+
+    fn workspace_id(&self, name: &str) -> Option<i64> {
+        self.workspaces
+            .values()
+            .find(|w| w.name == name)
+            .map(|w| w.id)
+    }
+}
+
+/// The floating windows on screen and where they are
+fn floating(clients: &[Client]) -> HashMap<Address, Rect> {
+    clients
+        .iter()
+        .filter(|c| c.mapped && c.floating)
+        .map(|c| (c.address, c.area()))
+        .collect()
+}
+
+/// The instance directory in use, looked up again once its sockets stop answering
+static INSTANCE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+fn runtime_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|v| !v.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => Some(PathBuf::from(format!("/run/user/{}", unsafe {
+            libc::getuid()
+        }))),
+    }
+}
+
+/// Finds the sockets of the running Hyprland. The instance from `HYPRLAND_INSTANCE_SIGNATURE`
+/// is used while it is alive; after a compositor restart it is the newest live instance instead.
+fn find_instance() -> Option<PathBuf> {
+    let hypr = runtime_dir()?.join("hypr");
+    if let Some(signature) = std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE") {
+        let dir = hypr.join(signature);
+        if is_alive(&dir) {
+            return Some(dir);
+        }
+    }
+    fs::read_dir(&hypr)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|dir| is_alive(dir))
+        .max_by_key(|dir| fs::metadata(dir).and_then(|m| m.modified()).ok())
+}
+
+/// Whether an instance directory belongs to a running Hyprland rather than a crashed one
+fn is_alive(dir: &Path) -> bool {
+    // Hyprland writes its pid on the first line of the lock file
+    match fs::read_to_string(dir.join("hyprland.lock")) {
+        Ok(lock) => {
+            dir.join(".socket.sock").exists()
+                && lock
+                    .lines()
+                    .next()
+                    .and_then(|pid| pid.trim().parse::<i32>().ok())
+                    .is_some_and(|pid| Path::new(&format!("/proc/{pid}")).exists())
+        }
+        Err(_) => UnixStream::connect(dir.join(".socket.sock")).is_ok(),
+    }
+}
+
+fn instance() -> Option<PathBuf> {
+    if let Some(dir) = INSTANCE.read().unwrap().clone() {
+        return Some(dir);
+    }
+    let dir = find_instance()?;
+    *INSTANCE.write().unwrap() = Some(dir.clone());
+    Some(dir)
+}
+
+/// Makes the next connection look for the instance again
+fn forget_instance() {
+    *INSTANCE.write().unwrap() = None;
+}
+
+/// Helper to communicate with Hyprland Socket instead of spawning processes.
+/// Sends a raw request such as `j/monitors` and returns the reply.
+pub fn hypr_query(cmd: &str) -> Option<String> {
+    let connect = |dir: PathBuf| UnixStream::connect(dir.join(".socket.sock")).ok();
+    let mut stream = match connect(instance()?) {
+        Some(stream) => stream,
+        None => {
+            forget_instance();
+            connect(instance()?)?
+        }
+    };
+    stream.write_all(cmd.as_bytes()).ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    Some(response)
+}
+
+/// `j/cursorpos`: where the cursor is in the layout, `None` when Hyprland cannot be reached or replies with something else
+pub fn get_cursor_pos() -> Option<CursorPos> {
+    serde_json::from_str(&hypr_query("j/cursorpos")?).ok()
+}
+
+/// `j/monitors`, `None` when Hyprland cannot be reached or replies with something else
+pub fn get_monitors() -> Option<Vec<Monitor>> {
+    serde_json::from_str(&hypr_query("j/monitors")?).ok()
+}
+
+/// `j/workspaces`, `None` when Hyprland cannot be reached or replies with something else
+pub fn get_workspaces() -> Option<Vec<Workspace>> {
+    serde_json::from_str(&hypr_query("j/workspaces")?).ok()
+}
+
+/// `j/clients`, `None` when Hyprland cannot be reached or replies with something else
+pub fn get_clients() -> Option<Vec<Client>> {
+    serde_json::from_str(&hypr_query("j/clients")?).ok()
+}
+
+/// Connects to `.socket2.sock` and yields the events the daemon understands, until the socket closes
+pub fn events() -> Option<impl Iterator<Item = HyprEvent>> {
+    let stream = UnixStream::connect(instance()?.join(".socket2.sock")).ok()?;
+    Some(
+        BufReader::new(stream)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| HyprEvent::parse(&line)),
+    )
+}
+
+/// Queries everything the window model is built from
+pub fn query_window_model() -> Option<WindowModel> {
+    let clients = get_clients()?;
+    let workspaces = get_workspaces()?;
+    let monitors = get_monitors()?;
+    Some(WindowModel::new(&clients, &monitors, &workspaces))
+}
+
+/// Hyprland, over `.socket.sock` for queries and `.socket2.sock` for events
+pub struct Hyprland;
+
+impl Hyprland {
+    /// Whether a live instance can be found, with or without `HYPRLAND_INSTANCE_SIGNATURE`
+    pub fn is_running() -> bool {
+        instance().is_some()
+    }
+}
+
+impl Compositor for Hyprland {
+    fn name(&self) -> &'static str {
+        "Hyprland"
+    }
+
+    fn cursor_pos(&self) -> Option<CursorPos> {
+        get_cursor_pos()
+    }
+
+    fn monitors(&self) -> Option<Vec<Monitor>> {
+        get_monitors()
+    }
+
+    fn outputs(&self) -> Option<Outputs> {
+        Some(query_window_model()?.outputs())
+    }
+
+    /// The instance is looked up again on every connection, to follow Hyprland across restarts
+    fn listen(&self, tx: Sender<Event>, monitors: MonitorLayout, geometry: Arc<AtomicBool>) {
+        compositor::reconnecting(
+            self.name(),
+            || {
+                forget_instance();
+                events()
+            },
+            |events| follow_events(events, &tx, &monitors, &geometry),
+        );
+    }
+}
+
+/// Applies the events of `.socket2.sock` to the window model, querying it again when an event
+/// cannot be applied on its own
+fn follow_events(
+    events: impl Iterator<Item = HyprEvent>,
+    tx: &Sender<Event>,
+    monitors: &MonitorLayout,
+    geometry: &AtomicBool,
+) -> bool {
+    // Anything may have changed while disconnected
+    if let Some(fresh) = get_monitors() {
+        *monitors.write().unwrap() = fresh;
+    }
+    let mut model = query_window_model().unwrap_or_default();
+    let mut sender = OutputsSender::new(tx);
+    if !sender.send(model.outputs()) {
+        return false;
+    }
+
+    // The model starts from a fresh `j/clients`, geometry included
+    let mut had_geometry = true;
+    for event in events {
+        // Windows that moved while it was not wanted are somewhere else by now
+        let wants_geometry = geometry.load(Ordering::Relaxed);
+        let stale_geometry = wants_geometry && !had_geometry;
+        had_geometry = wants_geometry;
+        if event.changes_monitors()
+            && let Some(fresh) = get_monitors()
+        {
+            *monitors.write().unwrap() = fresh;
+        }
+        if !model.apply(&event)
+            && let Some(fresh) = query_window_model()
+        {
+            model = fresh;
+        } else if (stale_geometry || wants_geometry && event.moves_windows())
+            && let Some(clients) = get_clients()
+        {
+            model.update_geometry(&clients);
+        }
+        let outputs = model.outputs();
+        // Ahead of the windows of the new workspace, so a peek starts before they can hide the bar
+        if matches!(
+            event,
+            HyprEvent::Workspace { .. } | HyprEvent::FocusedMonitor { .. }
+        ) && tx
+            .send(Event::WorkspaceSwitched(outputs.focused.clone()))
+            .is_err()
+        {
+            return false;
+        }
+        if !sender.send(outputs) {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workspace_events() {
+        assert_eq!(
+            HyprEvent::parse("workspacev2>>3,3"),
+            Some(HyprEvent::Workspace {
+                id: 3,
+                name: "3".into()
+            })
+        );
+        assert_eq!(
+            HyprEvent::parse("focusedmonv2>>DP-1,5"),
+            Some(HyprEvent::FocusedMonitor {
+                monitor: "DP-1".into(),
+                workspace_id: 5
+            })
+        );
+        assert_eq!(
+            HyprEvent::parse("moveworkspacev2>>2,web, mail,HDMI-A-1"),
+            Some(HyprEvent::MoveWorkspace {
+                id: 2,
+                name: "web, mail".into(),
+                monitor: "HDMI-A-1".into()
+            })
+        );
+        assert_eq!(
+            HyprEvent::parse("activespecial>>special:term,eDP-1"),
+            Some(HyprEvent::ActiveSpecial {
+                name: "special:term".into(),
+                monitor: "eDP-1".into()
+            })
+        );
+        assert_eq!(
+            HyprEvent::parse("activespecial>>,eDP-1"),
+            Some(HyprEvent::ActiveSpecial {
+                name: String::new(),
+                monitor: "eDP-1".into()
+            })
+        );
+    }
+
+    #[test]
+    fn window_events() {
+        assert_eq!(
+            HyprEvent::parse("openwindow>>5e4f1a2b3c40,2,kitty,vim: a, b >> c"),
+            Some(HyprEvent::OpenWindow {
+                address: 0x5e4f1a2b3c40,
+                workspace: "2".into(),
+                class: "kitty".into(),
+                title: "vim: a, b >> c".into()
+            })
+        );
+        assert_eq!(
+            HyprEvent::parse("closewindow>>5e4f1a2b3c40"),
+            Some(HyprEvent::CloseWindow {
+                address: 0x5e4f1a2b3c40
+            })
+        );
+        assert_eq!(
+            HyprEvent::parse("movewindowv2>>5e4f1a2b3c40,4,special:scratch"),
+            Some(HyprEvent::MoveWindow {
+                address: 0x5e4f1a2b3c40,
+                workspace_id: 4,
+                workspace: "special:scratch".into()
+            })
+        );
+        assert_eq!(
+            HyprEvent::parse("changefloatingmode>>5e4f1a2b3c40,1"),
+            Some(HyprEvent::ChangeFloatingMode {
+                address: 0x5e4f1a2b3c40,
+                floating: true
+            })
+        );
+        assert_eq!(
+            HyprEvent::parse("activewindowv2>>"),
+            Some(HyprEvent::ActiveWindow { address: None })
+        );
+        assert_eq!(
+            HyprEvent::parse("fullscreen>>1"),
+            Some(HyprEvent::Fullscreen(true))
+        );
+    }
+
+    #[test]
+    fn titles_do_not_look_like_events() {
+        // The old substring matching reacted to these
+        assert_eq!(
+            HyprEvent::parse("windowtitlev2>>5e4f,my workspace window"),
+            None
+        );
+        assert_eq!(
+            HyprEvent::parse("activewindow>>firefox,monitor review"),
+            None
+        );
+    }
+
+    #[test]
+    fn v1_duplicates_and_garbage_are_ignored() {
+        assert_eq!(HyprEvent::parse("workspace>>3"), None);
+        assert_eq!(HyprEvent::parse("movewindow>>5e4f,2"), None);
+        assert_eq!(HyprEvent::parse("workspacev2>>three,3"), None);
+        assert_eq!(HyprEvent::parse("closewindow>>not-hex"), None);
+        assert_eq!(HyprEvent::parse("no separator"), None);
+    }
+
+    #[test]
+    fn monitor_events() {
+        assert!(
+            HyprEvent::parse("monitoradded>>DP-2")
+                .unwrap()
+                .changes_monitors()
+        );
+        assert!(
+            HyprEvent::parse("configreloaded>>")
+                .unwrap()
+                .changes_monitors()
+        );
+        assert!(
+            !HyprEvent::parse("closewindow>>5e4f")
+                .unwrap()
+                .changes_monitors()
+        );
+    }
+
+    #[test]
+    fn addresses_with_or_without_prefix() {
+        assert_eq!(address("0x5e4f"), Some(0x5e4f));
+        assert_eq!(address("5e4f"), Some(0x5e4f));
+    }
+
+    fn model() -> WindowModel {
+        let clients: Vec<Client> = serde_json::from_str(
+            r#"[
+                {"address": "0xa1", "workspace": {"id": 1, "name": "1"}, "mapped": true},
+                {"address": "0xa2", "workspace": {"id": -98, "name": "special:term"}, "mapped": true},
+                {"address": "0xa3", "workspace": {"id": -1, "name": ""}, "mapped": false}
+            ]"#,
+        )
+        .unwrap();
+        let monitors: Vec<Monitor> = serde_json::from_str(
+            r#"[
+                {"name": "DP-1", "focused": true, "activeWorkspace": {"id": 1, "name": "1"},
+                 "x": 0, "y": 0, "width": 1920, "height": 1080},
+                {"name": "DP-2", "focused": false, "activeWorkspace": {"id": 2, "name": "2"},
+                 "x": 1920, "y": 0, "width": 1920, "height": 1080}
+            ]"#,
+        )
+        .unwrap();
+        let workspaces: Vec<Workspace> = serde_json::from_str(
+            r#"[
+                {"id": 1, "name": "1", "monitor": "DP-1"},
+                {"id": 2, "name": "2", "monitor": "DP-2"},
+                {"id": -98, "name": "special:term", "monitor": "DP-1"}
+            ]"#,
+        )
+        .unwrap();
+        WindowModel::new(&clients, &monitors, &workspaces)
+    }
+
+    fn windows(model: &WindowModel, monitor: &str) -> bool {
+        model.outputs().windows[monitor]
+    }
+
+    fn apply(model: &mut WindowModel, line: &str) -> bool {
+        model.apply(&HyprEvent::parse(line).unwrap())
+    }
+
+    #[test]
+    fn model_starts_from_queries() {
+        let model = model();
+        assert!(windows(&model, "DP-1"));
+        assert!(!windows(&model, "DP-2"));
+        assert_eq!(model.outputs().focused.as_deref(), Some("DP-1"));
+    }
+
+    #[test]
+    fn model_follows_windows() {
+        let mut model = model();
+        assert!(apply(&mut model, "openwindow>>b1,2,kitty,shell"));
+        assert!(windows(&model, "DP-2"));
+        assert!(apply(&mut model, "movewindowv2>>b1,1,1"));
+        assert!(!windows(&model, "DP-2"));
+        assert!(apply(&mut model, "closewindow>>a1"));
+        assert!(apply(&mut model, "closewindow>>b1"));
+        assert!(!windows(&model, "DP-1"));
+    }
+
+    #[test]
+    fn model_follows_workspaces_and_focus() {
+        let mut model = model();
+        // Workspace 2 lives on DP-2, switching to it focuses that monitor
+        assert!(apply(&mut model, "workspacev2>>2,2"));
+        assert_eq!(model.outputs().focused.as_deref(), Some("DP-2"));
+        assert!(windows(&model, "DP-1"));
+
+        assert!(apply(&mut model, "createworkspacev2>>3,3"));
+        assert!(apply(&mut model, "workspacev2>>3,3"));
+        assert!(apply(&mut model, "openwindow>>c1,3,foot,"));
+        assert!(windows(&model, "DP-2"));
+
+        assert!(apply(&mut model, "focusedmonv2>>DP-1,1"));
+        assert_eq!(model.outputs().focused.as_deref(), Some("DP-1"));
+    }
+
+    #[test]
+    fn special_workspace_counts_while_open() {
+        let mut model = model();
+        assert!(apply(&mut model, "closewindow>>a1"));
+        assert!(!windows(&model, "DP-1"));
+        assert!(apply(&mut model, "activespecial>>special:term,DP-1"));
+        assert!(windows(&model, "DP-1"));
+        assert!(apply(&mut model, "activespecial>>,DP-1"));
+        assert!(!windows(&model, "DP-1"));
+    }
+
+    #[test]
+    fn floating_windows_keep_their_geometry() {
+        let mut model = model();
+        let clients: Vec<Client> = serde_json::from_str(
+            r#"[
+                {"address": "0xa1", "workspace": {"id": 1, "name": "1"}, "floating": true,
+                 "at": [700, 400], "size": [500, 300]},
+                {"address": "0xe1", "workspace": {"id": 1, "name": "1"}, "floating": true,
+                 "at": [0, 0], "size": [100, 100]}
+            ]"#,
+        )
+        .unwrap();
+        model.update_geometry(&clients);
+        let area = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        // Unknown to the model until `openwindow` arrives
+        assert_eq!(model.outputs().areas["DP-1"], [area(700, 400, 500, 300)]);
+        // Tiled windows cover their monitor
+        assert!(apply(&mut model, "openwindow>>b1,2,kitty,shell"));
+        assert_eq!(model.outputs().areas["DP-2"], [area(1920, 0, 1920, 1080)]);
+        assert!(apply(&mut model, "changefloatingmode>>a1,0"));
+        assert_eq!(model.outputs().areas["DP-1"], [area(0, 0, 1920, 1080)]);
+        assert!(
+            HyprEvent::parse("changefloatingmode>>a1,1")
+                .unwrap()
+                .moves_windows()
+        );
+        assert!(!HyprEvent::parse("closewindow>>a1").unwrap().moves_windows());
+    }
+
+    #[test]
+    fn model_asks_for_a_resync_when_lost() {
+        let mut model = model();
+        assert!(!apply(&mut model, "openwindow>>d1,unknown,foot,"));
+        assert!(!apply(&mut model, "workspacev2>>7,7"));
+        assert!(!apply(&mut model, "moveworkspacev2>>2,2,DP-1"));
+        assert!(!apply(&mut model, "monitoradded>>HDMI-A-1"));
+    }
+}
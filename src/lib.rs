@@ -0,0 +1,68 @@
+//! Hides Waybar while windows are open and reveals it when the cursor reaches a screen edge.
+//!
+//! The binary is a thin wrapper around [`daemon::run`]. The pieces it is built from can be used
+//! on their own, for instance to embed the behaviour in another session daemon:
+//!
+//! - [`hyprland`] talks to Hyprland: raw requests with [`hyprland::hypr_query`], typed queries
+//!   and the event stream of `.socket2.sock`, parsed into [`hyprland::HyprEvent`].
+//! - [`compositor`] puts Hyprland, niri, Sway and other Wayland compositors behind the
+//!   [`compositor::Compositor`] trait, and [`hotzone`] follows the cursor where they cannot
+//!   tell where it is.
+//! - [`visibility`] decides whether a bar should be shown, without any I/O.
+//! - [`bar`] finds the Waybar processes of a bar and signals them.
+//!
+//! ```no_run
+//! use waybar_auto_hide::{
+//!     Outputs,
+//!     visibility::{Inputs, VisibilityEngine},
+//! };
+//!
+//! let outputs = Outputs::default();
+//! let mut engine = VisibilityEngine::default();
+//! let decision = engine.decide(&Inputs {
+//!     at_edge: false,
+//!     output: Some("DP-1"),
+//!     outputs: &outputs,
+//!     pinned: false,
+//!     peeking: false,
+//!     bar_areas: None,
+//! });
+//! println!("visible: {}, because of {:?}", decision.visible, decision.reason);
+//! ```
+
+/// The bars the daemon drives and the Waybar processes behind them
+pub mod bar;
+/// Command line parsing, only meant for the binary
+#[doc(hidden)]
+pub mod cli;
+/// The compositor backends behind a common trait
+pub mod compositor;
+/// The TOML config, its defaults and its validation
+pub mod config;
+/// The control socket, only meant for the binary
+#[doc(hidden)]
+pub mod control;
+/// The event loop tying the compositor, the config and the bars together
+pub mod daemon;
+/// The `doctor` command, only meant for the binary
+#[doc(hidden)]
+pub mod doctor;
+/// Following the cursor over layer-shell surfaces, for compositors that do not report it
+pub mod hotzone;
+/// Hyprland's sockets: queries, events and the window model built from them
+pub mod hyprland;
+/// Monitors and the geometry of the layout
+pub mod monitor;
+/// niri's JSON socket
+pub mod niri;
+mod reload;
+/// Sway and other compositors speaking the i3 IPC protocol
+pub mod sway;
+/// Whether a bar should be shown, without any I/O
+pub mod visibility;
+/// Waybar configs: where bars sit and which signals they react to
+pub mod waybar;
+/// Plain Wayland, for compositors without an IPC of their own
+pub mod wayland;
+
+pub use daemon::{CursorState, Event, MonitorLayout, Outputs, ZoneLayout};
@@ -0,0 +1,292 @@
+use crate::config::Edge;
+use serde::{Deserialize, Serialize};
+
+/// A monitor as reported by `j/monitors`.
+/// `x` and `y` are in the logical layout space, like the cursor, while `width` and `height`
+/// are the pixel size of the mode, before scaling and rotation.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Monitor {
+    /// The connector name, such as `DP-1`
+    pub name: String,
+    /// Whether it has the keyboard focus
+    #[serde(default)]
+    pub focused: bool,
+    /// The workspace it shows
+    #[serde(default)]
+    pub active_workspace: WorkspaceRef,
+    /// The special workspace open on top, with an id of 0 when there is none
+    #[serde(default)]
+    pub special_workspace: WorkspaceRef,
+    /// Left edge in the layout
+    pub x: i32,
+    /// Top edge in the layout
+    pub y: i32,
+    /// Width of the mode in pixels
+    pub width: i32,
+    /// Height of the mode in pixels
+    pub height: i32,
+    /// How many pixels of the mode make a logical pixel
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    /// `wl_output` transform: 0 to 3 rotate by 90° steps, 4 to 7 flip first
+    #[serde(default)]
+    pub transform: u8,
+    /// Space taken by exclusive layers (bars, docks) as left, top, right, bottom
+    #[serde(default)]
+    pub reserved: [i32; 4],
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+/// A workspace as monitors refer to it
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct WorkspaceRef {
+    /// Hyprland's workspace id, 0 elsewhere
+    pub id: i64,
+    /// The name shown to the user
+    #[serde(default)]
+    pub name: String,
+}
+
+/// An area of the layout, in logical pixels
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    /// Left edge
+    pub x: i32,
+    /// Top edge
+    pub y: i32,
+    /// Width, the right edge is `x + width` and outside the area
+    pub width: i32,
+    /// Height, the bottom edge is `y + height` and outside the area
+    pub height: i32,
+}
+
+impl Rect {
+    /// Whether a point lies inside the area
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// Whether the two areas share at least one pixel
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.x + other.width
+            && other.x < self.x + self.width
+            && self.y < other.y + other.height
+            && other.y < self.y + self.height
+    }
+
+    /// The band of the given depth along one edge of this area
+    pub fn strip(&self, edge: Edge, depth: i32) -> Rect {
+        let depth = depth.clamp(
+            0,
+            if matches!(edge, Edge::Top | Edge::Bottom) {
+                self.height
+            } else {
+                self.width
+            },
+        );
+        match edge {
+            Edge::Top => Rect {
+                height: depth,
+                ..*self
+            },
+            Edge::Bottom => Rect {
+                y: self.y + self.height - depth,
+                height: depth,
+                ..*self
+            },
+            Edge::Left => Rect {
+                width: depth,
+                ..*self
+            },
+            Edge::Right => Rect {
+                x: self.x + self.width - depth,
+                width: depth,
+                ..*self
+            },
+        }
+    }
+}
+
+impl Monitor {
+    /// A monitor whose position and size are already final in the layout, so it is described
+    /// as unscaled and unrotated
+    pub fn logical(name: String, x: i32, y: i32, width: i32, height: i32) -> Monitor {
+        Monitor {
+            name,
+            focused: false,
+            active_workspace: WorkspaceRef::default(),
+            special_workspace: WorkspaceRef::default(),
+            x,
+            y,
+            width,
+            height,
+            scale: 1.0,
+            transform: 0,
+            reserved: [0; 4],
+        }
+    }
+
+    /// The area the monitor covers in the layout, which is what the cursor position is relative to
+    pub fn bounds(&self) -> Rect {
+        // Odd transforms turn the monitor sideways
+        let (width, height) = if self.transform % 2 == 1 {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        let scale = if self.scale > 0.0 { self.scale } else { 1.0 };
+        Rect {
+            x: self.x,
+            y: self.y,
+            width: (width as f64 / scale).round() as i32,
+            height: (height as f64 / scale).round() as i32,
+        }
+    }
+
+    /// The space exclusive layers reserve along an edge
+    pub fn reserved(&self, edge: Edge) -> i32 {
+        let [left, top, right, bottom] = self.reserved;
+        match edge {
+            Edge::Top => top,
+            Edge::Bottom => bottom,
+            Edge::Left => left,
+            Edge::Right => right,
+        }
+    }
+}
+
+/// The monitor under a point of the layout
+pub fn at(monitors: &[Monitor], x: i32, y: i32) -> Option<&Monitor> {
+    monitors.iter().find(|m| m.bounds().contains(x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(json: &str) -> Monitor {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn plain_monitor_keeps_its_mode_size() {
+        let m = monitor(r#"{"name": "DP-1", "x": 0, "y": 0, "width": 1920, "height": 1080}"#);
+        assert_eq!(
+            m.bounds(),
+            Rect {
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080
+            }
+        );
+    }
+
+    #[test]
+    fn fractional_scale_shrinks_the_logical_size() {
+        let m = monitor(
+            r#"{"name": "eDP-1", "x": 0, "y": 0, "width": 2880, "height": 1800, "scale": 1.5}"#,
+        );
+        assert_eq!((m.bounds().width, m.bounds().height), (1920, 1200));
+
+        // 2560 / 1.25 = 2048, 1600 / 1.25 = 1280
+        let m = monitor(
+            r#"{"name": "eDP-1", "x": 0, "y": 0, "width": 2560, "height": 1600, "scale": 1.25}"#,
+        );
+        assert_eq!((m.bounds().width, m.bounds().height), (2048, 1280));
+    }
+
+    #[test]
+    fn rotated_monitor_swaps_width_and_height() {
+        for transform in [1, 3, 5, 7] {
+            let m = monitor(&format!(
+                r#"{{"name": "DP-2", "x": 1920, "y": 0, "width": 2560, "height": 1440, "transform": {transform}}}"#
+            ));
+            assert_eq!((m.bounds().width, m.bounds().height), (1440, 2560));
+        }
+        for transform in [0, 2, 4, 6] {
+            let m = monitor(&format!(
+                r#"{{"name": "DP-2", "x": 1920, "y": 0, "width": 2560, "height": 1440, "transform": {transform}}}"#
+            ));
+            assert_eq!((m.bounds().width, m.bounds().height), (2560, 1440));
+        }
+    }
+
+    #[test]
+    fn rotated_and_scaled_monitor() {
+        let m = monitor(
+            r#"{"name": "DP-2", "x": 1280, "y": 0, "width": 3840, "height": 2160, "scale": 1.5, "transform": 1}"#,
+        );
+        assert_eq!(
+            m.bounds(),
+            Rect {
+                x: 1280,
+                y: 0,
+                width: 1440,
+                height: 2560
+            }
+        );
+    }
+
+    #[test]
+    fn cursor_is_matched_against_logical_bounds() {
+        // A 1.5x laptop panel next to a portrait monitor
+        let monitors = vec![
+            monitor(
+                r#"{"name": "eDP-1", "x": 0, "y": 0, "width": 2880, "height": 1800, "scale": 1.5}"#,
+            ),
+            monitor(
+                r#"{"name": "DP-1", "x": 1920, "y": 0, "width": 2560, "height": 1440, "transform": 3}"#,
+            ),
+        ];
+        // Past the physical width of the laptop panel but within its logical one
+        assert_eq!(at(&monitors, 1900, 10).unwrap().name, "eDP-1");
+        // The first logical column of the next monitor
+        assert_eq!(at(&monitors, 1920, 10).unwrap().name, "DP-1");
+        // Below the laptop panel, only the portrait monitor is that tall
+        assert_eq!(at(&monitors, 2000, 2000).unwrap().name, "DP-1");
+        assert!(at(&monitors, 100, 1300).is_none());
+    }
+
+    #[test]
+    fn strips_along_each_edge() {
+        let screen = Rect {
+            x: 1920,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        };
+        let top = screen.strip(Edge::Top, 30);
+        assert_eq!((top.x, top.y, top.width, top.height), (1920, 0, 1920, 30));
+        let bottom = screen.strip(Edge::Bottom, 30);
+        assert_eq!((bottom.y, bottom.height), (1050, 30));
+        let right = screen.strip(Edge::Right, 40);
+        assert_eq!((right.x, right.width, right.height), (3800, 40, 1080));
+
+        let window = |x, y| Rect {
+            x,
+            y,
+            width: 400,
+            height: 300,
+        };
+        assert!(top.intersects(&window(2000, 29)));
+        // Touching is not overlapping
+        assert!(!top.intersects(&window(2000, 30)));
+        assert!(!top.intersects(&window(1520, 0)));
+        assert!(right.intersects(&window(3500, 500)));
+    }
+
+    #[test]
+    fn reserved_is_left_top_right_bottom() {
+        let m = monitor(
+            r#"{"name": "DP-1", "x": 0, "y": 0, "width": 1920, "height": 1080, "reserved": [0, 30, 0, 40]}"#,
+        );
+        assert_eq!(m.reserved(Edge::Top), 30);
+        assert_eq!(m.reserved(Edge::Bottom), 40);
+        assert_eq!(m.reserved(Edge::Left), 0);
+    }
+}
@@ -0,0 +1,371 @@
+use crate::{
+    Event, MonitorLayout, Outputs,
+    compositor::{self, Compositor, CursorPos, OutputsSender},
+    monitor::Monitor,
+};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    sync::{Arc, atomic::AtomicBool, mpsc::Sender},
+};
+
+/// niri, over the JSON socket at `$NIRI_SOCKET`
+pub struct Niri {
+    socket: PathBuf,
+}
+
+impl Niri {
+    /// Finds the compositor's socket through the environment, `None` when not running under it
+    pub fn from_env() -> Option<Niri> {
+        let socket = std::env::var_os("NIRI_SOCKET").filter(|v| !v.is_empty())?;
+        Some(Niri {
+            socket: PathBuf::from(socket),
+        })
+    }
+
+    /// Sends a request such as `"Outputs"` and unwraps the `{"Ok": {"Outputs": ...}}` reply
+    fn request<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        let mut stream = UnixStream::connect(&self.socket).ok()?;
+        stream.write_all(format!("\"{name}\"\n").as_bytes()).ok()?;
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).ok()?;
+        let mut reply: Value = serde_json::from_str(&line).ok()?;
+        serde_json::from_value(reply.get_mut("Ok")?.get_mut(name)?.take()).ok()
+    }
+
+    /// Follows `EventStream`, which starts with every workspace and window and then tells
+    /// what changed
+    fn follow_events(
+        &self,
+        mut stream: UnixStream,
+        tx: &Sender<Event>,
+        monitors: &MonitorLayout,
+    ) -> bool {
+        if stream.write_all(b"\"EventStream\"\n").is_err() {
+            return true;
+        }
+        let mut lines = BufReader::new(stream).lines().map_while(Result::ok);
+        // The first line only acknowledges the request
+        if lines.next().is_none() {
+            return true;
+        }
+
+        if let Some(fresh) = self.monitors() {
+            *monitors.write().unwrap() = fresh;
+        }
+        // The stream starts with the full list of workspaces and windows
+        let mut state = NiriState::default();
+        let mut sender = OutputsSender::new(tx);
+        for line in lines {
+            let Ok(event) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            match state.apply(&event) {
+                Applied::Outputs => {
+                    if let Some(fresh) = self.monitors() {
+                        *monitors.write().unwrap() = fresh;
+                    }
+                }
+                Applied::Switched(output) => {
+                    if tx.send(Event::WorkspaceSwitched(output)).is_err() {
+                        return false;
+                    }
+                }
+                Applied::Windows | Applied::Nothing => {}
+            }
+            if !sender.send(state.outputs()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Compositor for Niri {
+    fn name(&self) -> &'static str {
+        "niri"
+    }
+
+    /// niri's IPC has no request for the pointer position
+    fn has_cursor(&self) -> bool {
+        false
+    }
+
+    fn cursor_pos(&self) -> Option<CursorPos> {
+        None
+    }
+
+    fn monitors(&self) -> Option<Vec<Monitor>> {
+        let outputs: HashMap<String, NiriOutput> = self.request("Outputs")?;
+        Some(
+            outputs
+                .into_values()
+                .filter_map(NiriOutput::into_monitor)
+                .collect(),
+        )
+    }
+
+    fn outputs(&self) -> Option<Outputs> {
+        let mut state = NiriState {
+            workspaces: self.request("Workspaces")?,
+            ..Default::default()
+        };
+        let windows: Vec<NiriWindow> = self.request("Windows")?;
+        state.set_windows(windows);
+        Some(state.outputs())
+    }
+
+    /// Window geometry is not reported, so there is nothing to skip
+    fn listen(&self, tx: Sender<Event>, monitors: MonitorLayout, _geometry: Arc<AtomicBool>) {
+        compositor::reconnecting(
+            self.name(),
+            || UnixStream::connect(&self.socket).ok(),
+            |stream| self.follow_events(stream, &tx, &monitors),
+        );
+    }
+}
+
+#[derive(Deserialize)]
+struct NiriOutput {
+    name: String,
+    /// Unset while the output is disabled
+    logical: Option<Logical>,
+}
+
+/// Position and size in the layout space, after scaling and rotation
+#[derive(Deserialize)]
+struct Logical {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl NiriOutput {
+    /// Disabled outputs have no logical size
+    fn into_monitor(self) -> Option<Monitor> {
+        let Logical {
+            x,
+            y,
+            width,
+            height,
+        } = self.logical?;
+        Some(Monitor::logical(self.name, x, y, width, height))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct NiriWorkspace {
+    id: u64,
+    output: Option<String>,
+    is_active: bool,
+    is_focused: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct NiriWindow {
+    id: u64,
+    workspace_id: Option<u64>,
+}
+
+/// What an event changed
+#[derive(Debug, PartialEq)]
+enum Applied {
+    Windows,
+    /// A workspace was activated on the given output
+    Switched(Option<String>),
+    /// Workspaces were rebuilt, which is what happens when outputs come and go
+    Outputs,
+    Nothing,
+}
+
+/// The workspaces and windows as told by the event stream
+#[derive(Debug, Default)]
+struct NiriState {
+    workspaces: Vec<NiriWorkspace>,
+    /// Window id to its workspace
+    windows: HashMap<u64, Option<u64>>,
+}
+
+impl NiriState {
+    fn set_windows(&mut self, windows: Vec<NiriWindow>) {
+        self.windows = windows
+            .into_iter()
+            .map(|w| (w.id, w.workspace_id))
+            .collect();
+    }
+
+    /// Follows one event of the stream, an object with the event name as its only key
+    fn apply(&mut self, event: &Value) -> Applied {
+        let Some((name, data)) = event.as_object().and_then(|o| o.iter().next()) else {
+            return Applied::Nothing;
+        };
+        let field = |key: &str| data.get(key).cloned().unwrap_or_default();
+        match name.as_str() {
+            "WorkspacesChanged" => {
+                let Ok(workspaces) = serde_json::from_value(field("workspaces")) else {
+                    return Applied::Nothing;
+                };
+                self.workspaces = workspaces;
+                Applied::Outputs
+            }
+            "WorkspaceActivated" => {
+                let (Some(id), focused) = (field("id").as_u64(), field("focused").as_bool()) else {
+                    return Applied::Nothing;
+                };
+                let Some(output) = self
+                    .workspaces
+                    .iter()
+                    .find(|w| w.id == id)
+                    .map(|w| w.output.clone())
+                else {
+                    return Applied::Nothing;
+                };
+                for ws in &mut self.workspaces {
+                    if ws.output == output {
+                        ws.is_active = ws.id == id;
+                    }
+                    if focused == Some(true) {
+                        ws.is_focused = ws.id == id;
+                    }
+                }
+                Applied::Switched(output)
+            }
+            "WindowsChanged" => {
+                let Ok(windows) = serde_json::from_value(field("windows")) else {
+                    return Applied::Nothing;
+                };
+                self.set_windows(windows);
+                Applied::Windows
+            }
+            "WindowOpenedOrChanged" => {
+                let Ok(window) = serde_json::from_value::<NiriWindow>(field("window")) else {
+                    return Applied::Nothing;
+                };
+                self.windows.insert(window.id, window.workspace_id);
+                Applied::Windows
+            }
+            "WindowClosed" => {
+                if let Some(id) = field("id").as_u64() {
+                    self.windows.remove(&id);
+                }
+                Applied::Windows
+            }
+            _ => Applied::Nothing,
+        }
+    }
+
+    fn outputs(&self) -> Outputs {
+        let occupied = |id: u64| self.windows.values().any(|&ws| ws == Some(id));
+        Outputs {
+            focused: self
+                .workspaces
+                .iter()
+                .find(|w| w.is_focused)
+                .and_then(|w| w.output.clone()),
+            windows: self
+                .workspaces
+                .iter()
+                .filter(|w| w.is_active)
+                .filter_map(|w| Some((w.output.clone()?, occupied(w.id))))
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(state: &mut NiriState, line: &str) -> Applied {
+        state.apply(&serde_json::from_str(line).unwrap())
+    }
+
+    fn initial() -> NiriState {
+        let mut state = NiriState::default();
+        apply(
+            &mut state,
+            r#"{"WorkspacesChanged": {"workspaces": [
+                {"id": 1, "idx": 1, "name": null, "output": "DP-1", "is_active": true, "is_focused": true, "active_window_id": 10},
+                {"id": 2, "idx": 2, "name": null, "output": "DP-1", "is_active": false, "is_focused": false, "active_window_id": null},
+                {"id": 3, "idx": 1, "name": "web", "output": "HDMI-A-1", "is_active": true, "is_focused": false, "active_window_id": null}
+            ]}}"#,
+        );
+        apply(
+            &mut state,
+            r#"{"WindowsChanged": {"windows": [
+                {"id": 10, "title": "foot", "app_id": "foot", "workspace_id": 1, "is_focused": true}
+            ]}}"#,
+        );
+        state
+    }
+
+    #[test]
+    fn windows_on_active_workspaces() {
+        let outputs = initial().outputs();
+        assert_eq!(outputs.focused.as_deref(), Some("DP-1"));
+        assert!(outputs.windows["DP-1"]);
+        assert!(!outputs.windows["HDMI-A-1"]);
+    }
+
+    #[test]
+    fn follows_workspace_switches() {
+        let mut state = initial();
+        assert_eq!(
+            apply(
+                &mut state,
+                r#"{"WorkspaceActivated": {"id": 2, "focused": true}}"#,
+            ),
+            Applied::Switched(Some("DP-1".to_string()))
+        );
+        assert!(!state.outputs().windows["DP-1"]);
+        apply(
+            &mut state,
+            r#"{"WorkspaceActivated": {"id": 3, "focused": true}}"#,
+        );
+        let outputs = state.outputs();
+        assert_eq!(outputs.focused.as_deref(), Some("HDMI-A-1"));
+        // Activating a workspace on another output leaves this one alone
+        assert!(state.workspaces.iter().any(|w| w.id == 2 && w.is_active));
+    }
+
+    #[test]
+    fn follows_windows() {
+        let mut state = initial();
+        apply(
+            &mut state,
+            r#"{"WindowOpenedOrChanged": {"window": {"id": 11, "title": "mpv", "app_id": "mpv", "workspace_id": 3, "is_focused": false}}}"#,
+        );
+        assert!(state.outputs().windows["HDMI-A-1"]);
+        apply(&mut state, r#"{"WindowClosed": {"id": 11}}"#);
+        assert!(!state.outputs().windows["HDMI-A-1"]);
+        assert_eq!(
+            apply(&mut state, r#"{"KeyboardLayoutSwitched": {"idx": 1}}"#),
+            Applied::Nothing
+        );
+    }
+
+    #[test]
+    fn outputs_use_logical_geometry() {
+        let outputs: HashMap<String, NiriOutput> = serde_json::from_str(
+            r#"{
+                "eDP-1": {"name": "eDP-1", "make": "BOE", "model": "", "physical_size": [300, 190],
+                          "current_mode": 0, "vrr_supported": false, "vrr_enabled": false,
+                          "logical": {"x": 0, "y": 0, "width": 1920, "height": 1200, "scale": 1.5, "transform": "Normal"}},
+                "DP-2": {"name": "DP-2", "logical": null}
+            }"#,
+        )
+        .unwrap();
+        let monitors: Vec<Monitor> = outputs
+            .into_values()
+            .filter_map(NiriOutput::into_monitor)
+            .collect();
+        assert_eq!(monitors.len(), 1);
+        assert_eq!(monitors[0].bounds().width, 1920);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{Event, config::ConfigSource};
+use std::{
+    ffi::CString,
+    fs::File,
+    io::Read,
+    mem,
+    os::{fd::FromRawFd, unix::ffi::OsStrExt},
+    sync::mpsc::Sender,
+    thread,
+};
+
+/// Blocks SIGHUP for the calling thread and every thread spawned after it,
+/// so it can be picked up synchronously by `spawn_sighup_listener`.
+/// Must be called before any other thread is started.
+pub fn block_sighup() -> libc::sigset_t {
+    unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGHUP);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+        set
+    }
+}
+
+/// Reloads the config every time the daemon receives SIGHUP
+pub fn spawn_sighup_listener(set: libc::sigset_t, source: ConfigSource, tx: Sender<Event>) {
+    thread::spawn(move || {
+        loop {
+            let mut signal = 0;
+            if unsafe { libc::sigwait(&set, &mut signal) } != 0 {
+                return;
+            }
+            if !reload(&source, &tx) {
+                return;
+            }
+        }
+    });
+}
+
+/// Reloads the config whenever the file is written, created or replaced.
+/// The parent directory is watched, since most editors save by renaming a new file over the old one.
+pub fn spawn_config_watcher(source: ConfigSource, tx: Sender<Event>) {
+    let Some(path) = source.file() else {
+        return;
+    };
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return;
+    };
+    let Ok(dir) = CString::new(dir.as_os_str().as_bytes()) else {
+        return;
+    };
+    let file_name = file_name.to_os_string();
+
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return;
+    }
+    let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE | libc::IN_DELETE;
+    if unsafe { libc::inotify_add_watch(fd, dir.as_ptr(), mask) } < 0 {
+        // Nothing to watch yet, the config directory does not exist
+        unsafe { libc::close(fd) };
+        return;
+    }
+    let mut inotify = unsafe { File::from_raw_fd(fd) };
+
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            let len = match inotify.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(len) => len,
+            };
+            // A single save usually produces several events, reload once per batch
+            if touched_names(&buf[..len]).any(|name| name == file_name.as_bytes())
+                && !reload(&source, &tx)
+            {
+                return;
+            }
+        }
+    });
+}
+
+/// Iterates over the file names carried by a buffer of raw `inotify_event`s
+fn touched_names(mut buf: &[u8]) -> impl Iterator<Item = &[u8]> {
+    const HEADER: usize = mem::size_of::<libc::inotify_event>();
+    std::iter::from_fn(move || {
+        if buf.len() < HEADER {
+            return None;
+        }
+        // `len` is the last field of the header and includes the NUL padding of the name
+        let len = u32::from_ne_bytes(buf[HEADER - 4..HEADER].try_into().ok()?) as usize;
+        let name = buf.get(HEADER..HEADER + len)?;
+        buf = &buf[HEADER + len..];
+        let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        Some(&name[..end])
+    })
+}
+
+/// Loads the config again and hands it to the event loop.
+/// An invalid config is only reported, the daemon keeps running with the previous one.
+/// Returns false once the event loop is gone.
+fn reload(source: &ConfigSource, tx: &Sender<Event>) -> bool {
+    match source.load() {
+        Ok(config) => tx.send(Event::ConfigReloaded(Box::new(config))).is_ok(),
+        Err(err) => {
+            eprintln!(
+                "waybar_auto_hide: config reload rejected, keeping the previous config: {err}"
+            );
+            true
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use crate::{
+    Event, MonitorLayout, Outputs,
+    compositor::{self, Compositor, CursorPos, OutputsSender},
+    monitor::{Monitor, WorkspaceRef},
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    sync::{Arc, atomic::AtomicBool, mpsc::Sender},
+};
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+
+const GET_WORKSPACES: u32 = 1;
+const SUBSCRIBE: u32 = 2;
+const GET_OUTPUTS: u32 = 3;
+const GET_TREE: u32 = 4;
+
+/// Events have the high bit of their type set
+const EVENT_WORKSPACE: u32 = 0x8000_0000;
+const EVENT_OUTPUT: u32 = 0x8000_0001;
+const EVENT_WINDOW: u32 = 0x8000_0003;
+
+/// Sway, or anything else speaking the i3 IPC protocol, over `$SWAYSOCK`
+pub struct Sway {
+    socket: PathBuf,
+}
+
+impl Sway {
+    /// Finds the compositor's socket through the environment, `None` when not running under it
+    pub fn from_env() -> Option<Sway> {
+        let socket = ["SWAYSOCK", "I3SOCK"]
+            .into_iter()
+            .filter_map(std::env::var_os)
+            .find(|v| !v.is_empty())?;
+        Some(Sway {
+            socket: PathBuf::from(socket),
+        })
+    }
+
+    /// Sends a single request on a fresh connection
+    fn request<T: for<'de> Deserialize<'de>>(&self, kind: u32) -> Option<T> {
+        let mut stream = UnixStream::connect(&self.socket).ok()?;
+        send(&mut stream, kind, b"").ok()?;
+        let (_, payload) = receive(&mut stream).ok()?;
+        serde_json::from_slice(&payload).ok()
+    }
+
+    /// Subscribes to window, workspace and output events, querying the windows shown again after
+    /// those that can change them
+    fn follow_events(
+        &self,
+        mut stream: UnixStream,
+        tx: &Sender<Event>,
+        monitors: &MonitorLayout,
+    ) -> bool {
+        let subscribed = send(
+            &mut stream,
+            SUBSCRIBE,
+            br#"["window","workspace","output"]"#,
+        )
+        .and_then(|_| receive(&mut stream))
+        .is_ok();
+        if !subscribed {
+            return true;
+        }
+
+        // Anything may have changed while disconnected
+        if let Some(fresh) = self.monitors() {
+            *monitors.write().unwrap() = fresh;
+        }
+        let mut sender = OutputsSender::new(tx);
+        if !sender.send(self.outputs().unwrap_or_default()) {
+            return false;
+        }
+
+        while let Ok((kind, payload)) = receive(&mut stream) {
+            match kind {
+                EVENT_OUTPUT => {
+                    if let Some(fresh) = self.monitors() {
+                        *monitors.write().unwrap() = fresh;
+                    }
+                }
+                EVENT_WINDOW => {
+                    // Title and focus changes do not move windows around
+                    let change: Change = serde_json::from_slice(&payload).unwrap_or_default();
+                    if !matches!(change.change.as_str(), "new" | "close" | "move") {
+                        continue;
+                    }
+                }
+                EVENT_WORKSPACE => {
+                    let change: Change = serde_json::from_slice(&payload).unwrap_or_default();
+                    if change.change == "focus" {
+                        let output = change.current.and_then(|ws| ws.output);
+                        if tx.send(Event::WorkspaceSwitched(output)).is_err() {
+                            return false;
+                        }
+                    }
+                }
+                _ => continue,
+            }
+            if let Some(outputs) = self.outputs()
+                && !sender.send(outputs)
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Compositor for Sway {
+    fn name(&self) -> &'static str {
+        "Sway"
+    }
+
+    /// The i3 IPC protocol has no way to query the pointer
+    fn has_cursor(&self) -> bool {
+        false
+    }
+
+    fn cursor_pos(&self) -> Option<CursorPos> {
+        None
+    }
+
+    fn monitors(&self) -> Option<Vec<Monitor>> {
+        let outputs: Vec<SwayOutput> = self.request(GET_OUTPUTS)?;
+        Some(
+            outputs
+                .into_iter()
+                .filter(|o| o.active)
+                .map(SwayOutput::into_monitor)
+                .collect(),
+        )
+    }
+
+    fn outputs(&self) -> Option<Outputs> {
+        let workspaces: Vec<SwayWorkspace> = self.request(GET_WORKSPACES)?;
+        let tree: Node = self.request(GET_TREE)?;
+        Some(outputs(&workspaces, &tree))
+    }
+
+    /// Window geometry is not reported, so there is nothing to skip
+    fn listen(&self, tx: Sender<Event>, monitors: MonitorLayout, _geometry: Arc<AtomicBool>) {
+        compositor::reconnecting(
+            self.name(),
+            || UnixStream::connect(&self.socket).ok(),
+            |stream| self.follow_events(stream, &tx, &monitors),
+        );
+    }
+}
+
+/// Writes one message: magic, payload length and type in native byte order, then the payload
+fn send(stream: &mut impl Write, kind: u32, payload: &[u8]) -> io::Result<()> {
+    let mut message = Vec::with_capacity(14 + payload.len());
+    message.extend_from_slice(MAGIC);
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload);
+    stream.write_all(&message)
+}
+
+/// Reads one reply or event, returning its type and payload
+fn receive(stream: &mut impl Read) -> io::Result<(u32, Vec<u8>)> {
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header)?;
+    if &header[..6] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an i3-ipc message",
+        ));
+    }
+    let len = u32::from_ne_bytes(header[6..10].try_into().unwrap()) as usize;
+    let kind = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload)?;
+    Ok((kind, payload))
+}
+
+#[derive(Deserialize, Default)]
+struct Change {
+    #[serde(default)]
+    change: String,
+    /// The workspace a `workspace` event is about
+    #[serde(default)]
+    current: Option<WorkspaceNode>,
+}
+
+#[derive(Deserialize)]
+struct WorkspaceNode {
+    #[serde(default)]
+    output: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Rect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+#[derive(Deserialize)]
+struct SwayOutput {
+    name: String,
+    #[serde(default)]
+    active: bool,
+    #[serde(default)]
+    focused: bool,
+    #[serde(default)]
+    current_workspace: Option<String>,
+    rect: Rect,
+}
+
+impl SwayOutput {
+    /// `rect` is already in logical pixels
+    fn into_monitor(self) -> Monitor {
+        let Rect {
+            x,
+            y,
+            width,
+            height,
+        } = self.rect;
+        Monitor {
+            focused: self.focused,
+            active_workspace: WorkspaceRef {
+                id: 0,
+                name: self.current_workspace.unwrap_or_default(),
+            },
+            ..Monitor::logical(self.name, x, y, width, height)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SwayWorkspace {
+    name: String,
+    visible: bool,
+    focused: bool,
+    output: String,
+}
+
+/// A node of `GET_TREE`: the root, outputs, workspaces, split containers and windows
+#[derive(Deserialize)]
+struct Node {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    floating_nodes: Vec<Node>,
+}
+
+impl Node {
+    fn children(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.iter().chain(&self.floating_nodes)
+    }
+
+    /// Windows are the containers without children
+    fn has_windows(&self) -> bool {
+        self.children().any(|child| {
+            matches!(child.kind.as_str(), "con" | "floating_con")
+                && (child.nodes.is_empty() && child.floating_nodes.is_empty()
+                    || child.has_windows())
+        })
+    }
+
+    /// Whether each workspace of the tree holds a window
+    fn occupied_workspaces(&self, found: &mut HashMap<String, bool>) {
+        if self.kind == "workspace" {
+            found.insert(self.name.clone().unwrap_or_default(), self.has_windows());
+            return;
+        }
+        for child in self.children() {
+            child.occupied_workspaces(found);
+        }
+    }
+}
+
+fn outputs(workspaces: &[SwayWorkspace], tree: &Node) -> Outputs {
+    let mut occupied = HashMap::new();
+    tree.occupied_workspaces(&mut occupied);
+    Outputs {
+        focused: workspaces
+            .iter()
+            .find(|w| w.focused)
+            .map(|w| w.output.clone()),
+        windows: workspaces
+            .iter()
+            .filter(|w| w.visible)
+            .map(|w| {
+                let windows = occupied.get(&w.name).copied().unwrap_or(false);
+                (w.output.clone(), windows)
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip() {
+        let mut buf = Vec::new();
+        send(&mut buf, SUBSCRIBE, br#"["window"]"#).unwrap();
+        assert_eq!(&buf[..6], b"i3-ipc");
+        let (kind, payload) = receive(&mut buf.as_slice()).unwrap();
+        assert_eq!(kind, SUBSCRIBE);
+        assert_eq!(payload, br#"["window"]"#);
+        assert!(receive(&mut &b"i3-ip"[..]).is_err());
+    }
+
+    #[test]
+    fn windows_per_visible_workspace() {
+        let workspaces: Vec<SwayWorkspace> = serde_json::from_str(
+            r#"[
+                {"name": "1", "visible": true, "focused": true, "output": "DP-1"},
+                {"name": "2", "visible": true, "focused": false, "output": "DP-2"},
+                {"name": "3", "visible": false, "focused": false, "output": "DP-2"}
+            ]"#,
+        )
+        .unwrap();
+        let tree: Node = serde_json::from_str(
+            r#"{"type": "root", "nodes": [
+                {"type": "output", "name": "__i3", "nodes": [
+                    {"type": "workspace", "name": "__i3_scratch", "floating_nodes": [
+                        {"type": "floating_con", "name": "hidden"}
+                    ]}
+                ]},
+                {"type": "output", "name": "DP-1", "nodes": [
+                    {"type": "workspace", "name": "1", "nodes": [
+                        {"type": "con", "name": null, "nodes": [
+                            {"type": "con", "name": "kitty"}
+                        ]}
+                    ]}
+                ]},
+                {"type": "output", "name": "DP-2", "nodes": [
+                    {"type": "workspace", "name": "2", "nodes": []},
+                    {"type": "workspace", "name": "3", "floating_nodes": [
+                        {"type": "floating_con", "name": "mpv"}
+                    ]}
+                ]}
+            ]}"#,
+        )
+        .unwrap();
+        let outputs = outputs(&workspaces, &tree);
+        assert_eq!(outputs.focused.as_deref(), Some("DP-1"));
+        assert!(outputs.windows["DP-1"]);
+        assert!(!outputs.windows["DP-2"]);
+    }
+
+    #[test]
+    fn outputs_are_already_logical() {
+        let output: SwayOutput = serde_json::from_str(
+            r#"{"name": "eDP-1", "active": true, "focused": true, "current_workspace": "1",
+                "scale": 1.5, "transform": "90",
+                "rect": {"x": 0, "y": 0, "width": 1200, "height": 1920}}"#,
+        )
+        .unwrap();
+        let bounds = output.into_monitor().bounds();
+        assert_eq!((bounds.width, bounds.height), (1200, 1920));
+    }
+}
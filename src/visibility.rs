@@ -0,0 +1,448 @@
+use crate::{Outputs, monitor::Rect};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Why a bar has its current visibility
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// The cursor is at the bar's edge of the screen
+    Cursor,
+    /// No window is open on the active workspace
+    EmptyWorkspace,
+    /// Windows are open on the active workspace
+    Windows,
+    /// With intellihide, windows are open but none of them covers the bar
+    Uncovered,
+    /// A `pin` command
+    Pin,
+    /// A `peek` command, or a workspace switch
+    Peek,
+    /// A `show`, `hide` or `toggle` command
+    Manual,
+}
+
+/// Everything the visibility of one bar depends on
+#[derive(Debug, Clone, Copy)]
+pub struct Inputs<'a> {
+    /// Whether the cursor is in one of the bar's reveal zones
+    pub at_edge: bool,
+    /// The monitor the bar is bound to, `None` to follow the focused one
+    pub output: Option<&'a str>,
+    /// Which monitors show windows, and which one is focused
+    pub outputs: &'a Outputs,
+    /// A `pin` is in place
+    pub pinned: bool,
+    /// A `peek` is running
+    pub peeking: bool,
+    /// With intellihide, where the bar is on the monitor it looks at
+    pub bar_areas: Option<&'a [Rect]>,
+}
+
+impl Inputs<'_> {
+    fn monitor(&self) -> Option<&str> {
+        self.output.or(self.outputs.focused.as_deref())
+    }
+
+    /// Whether the monitor the bar looks at shows windows
+    fn windows(&self) -> bool {
+        self.monitor()
+            .and_then(|name| self.outputs.windows.get(name))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Whether one of those windows covers the bar. Without intellihide, or without the
+    /// geometry of the windows, any window does.
+    fn covered(&self) -> bool {
+        let windows = self.monitor().and_then(|name| self.outputs.areas.get(name));
+        let (Some(bar), Some(windows)) = (self.bar_areas, windows) else {
+            return true;
+        };
+        windows
+            .iter()
+            .any(|window| bar.iter().any(|area| area.intersects(window)))
+    }
+}
+
+/// What the engine decided for a bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    /// Whether the bar should show
+    pub visible: bool,
+    /// Why
+    pub reason: Reason,
+}
+
+/// Decides the visibility of one bar, without any I/O.
+/// Pins win over peeks, which win over manual overrides, which win over the automatic decision.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisibilityEngine {
+    /// Set by `show`, `hide` and `toggle`, until the automatic decision changes
+    forced: Option<bool>,
+    last_auto: bool,
+}
+
+impl Default for VisibilityEngine {
+    /// Waybar starts visible
+    fn default() -> Self {
+        VisibilityEngine {
+            forced: None,
+            last_auto: true,
+        }
+    }
+}
+
+impl VisibilityEngine {
+    /// The manual override in place, if any
+    pub fn forced(&self) -> Option<bool> {
+        self.forced
+    }
+
+    /// Overrides the automatic decision until it changes
+    pub fn force(&mut self, visible: bool) {
+        self.forced = Some(visible);
+    }
+
+    /// Decides from the current inputs, dropping a manual override the automatic decision moved past
+    pub fn decide(&mut self, inputs: &Inputs) -> Decision {
+        let auto = automatic(inputs);
+        // A manual show/hide only lasts until the automatic decision changes
+        if auto.visible != self.last_auto {
+            self.forced = None;
+        }
+        self.last_auto = auto.visible;
+
+        if inputs.pinned {
+            Decision {
+                visible: true,
+                reason: Reason::Pin,
+            }
+        } else if inputs.peeking {
+            Decision {
+                visible: true,
+                reason: Reason::Peek,
+            }
+        } else if let Some(visible) = self.forced {
+            Decision {
+                visible,
+                reason: Reason::Manual,
+            }
+        } else {
+            auto
+        }
+    }
+}
+
+/// What a bar shows without any override.
+/// A bar bound to an output only looks at that output, an unbound one follows the focused monitor.
+fn automatic(inputs: &Inputs) -> Decision {
+    let (visible, reason) = if inputs.at_edge {
+        (true, Reason::Cursor)
+    } else if !inputs.windows() {
+        (true, Reason::EmptyWorkspace)
+    } else if !inputs.covered() {
+        (true, Reason::Uncovered)
+    } else {
+        (false, Reason::Windows)
+    };
+    Decision { visible, reason }
+}
+
+/// A flag that only follows its input once the input has held for a while.
+/// Time is passed in rather than read, so the event loop decides when to look again.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Delayed {
+    value: bool,
+    /// When the input started to differ from `value`, plus the delay it has to hold for
+    pending: Option<Instant>,
+}
+
+impl Delayed {
+    /// Starts settled on `value`
+    pub fn new(value: bool) -> Delayed {
+        Delayed {
+            value,
+            pending: None,
+        }
+    }
+
+    /// The value once the delay is over, not the latest input
+    pub fn value(&self) -> bool {
+        self.value
+    }
+
+    /// Feeds the current input and returns the delayed value. `delay` is how long the input
+    /// has to differ from the value before the value follows it.
+    pub fn update(&mut self, input: bool, delay: Duration, now: Instant) -> bool {
+        if input == self.value {
+            self.pending = None;
+            return self.value;
+        }
+        let deadline = *self.pending.get_or_insert(now + delay);
+        if now >= deadline {
+            self.value = input;
+            self.pending = None;
+        }
+        self.value
+    }
+
+    /// When the value will change if the input holds until then
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outputs(focused: Option<&str>, windows: &[(&str, bool)]) -> Outputs {
+        Outputs {
+            focused: focused.map(str::to_string),
+            windows: windows.iter().map(|&(n, w)| (n.to_string(), w)).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn inputs<'a>(outputs: &'a Outputs, at_edge: bool, pinned: bool, peeking: bool) -> Inputs<'a> {
+        Inputs {
+            at_edge,
+            output: None,
+            outputs,
+            pinned,
+            peeking,
+            bar_areas: None,
+        }
+    }
+
+    #[test]
+    fn every_combination() {
+        let with = outputs(Some("DP-1"), &[("DP-1", true)]);
+        let without = outputs(Some("DP-1"), &[("DP-1", false)]);
+        for windows in [false, true] {
+            for at_edge in [false, true] {
+                for pinned in [false, true] {
+                    for peeking in [false, true] {
+                        for forced in [None, Some(false), Some(true)] {
+                            let outputs = if windows { &with } else { &without };
+                            let inputs = inputs(outputs, at_edge, pinned, peeking);
+                            let auto = at_edge || !windows;
+                            // Primed with the same automatic decision, so the override holds
+                            let mut engine = VisibilityEngine {
+                                forced,
+                                last_auto: auto,
+                            };
+                            let expected = match (pinned, peeking, forced) {
+                                (true, _, _) => (true, Reason::Pin),
+                                (_, true, _) => (true, Reason::Peek),
+                                (_, _, Some(visible)) => (visible, Reason::Manual),
+                                _ if at_edge => (true, Reason::Cursor),
+                                _ if !windows => (true, Reason::EmptyWorkspace),
+                                _ => (false, Reason::Windows),
+                            };
+                            let decision = engine.decide(&inputs);
+                            assert_eq!(
+                                (decision.visible, decision.reason),
+                                expected,
+                                "windows {windows}, at edge {at_edge}, pinned {pinned}, peeking {peeking}, forced {forced:?}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bound_bars_ignore_the_focused_monitor() {
+        let outputs = outputs(Some("DP-1"), &[("DP-1", true), ("DP-2", false)]);
+        let mut engine = VisibilityEngine::default();
+        let mut inputs = inputs(&outputs, false, false, false);
+        assert_eq!(engine.decide(&inputs).reason, Reason::Windows);
+        inputs.output = Some("DP-2");
+        assert_eq!(engine.decide(&inputs).reason, Reason::EmptyWorkspace);
+        // Unknown monitors have no windows
+        inputs.output = Some("HDMI-A-1");
+        assert!(engine.decide(&inputs).visible);
+        let nothing = Outputs::default();
+        assert!(
+            VisibilityEngine::default()
+                .decide(&self::inputs(&nothing, false, false, false))
+                .visible
+        );
+    }
+
+    #[test]
+    fn overrides_last_until_the_automatic_decision_changes() {
+        let with = outputs(Some("DP-1"), &[("DP-1", true)]);
+        let without = outputs(Some("DP-1"), &[("DP-1", false)]);
+        let mut engine = VisibilityEngine::default();
+        assert!(!engine.decide(&inputs(&with, false, false, false)).visible);
+
+        engine.force(true);
+        // The cursor leaving or windows being focused again does not undo it...
+        let decision = engine.decide(&inputs(&with, false, false, false));
+        assert_eq!((decision.visible, decision.reason), (true, Reason::Manual));
+        // ...but the workspace emptying does
+        let decision = engine.decide(&inputs(&without, false, false, false));
+        assert_eq!(decision.reason, Reason::EmptyWorkspace);
+        assert_eq!(engine.forced(), None);
+
+        // A pin hides the override without clearing it
+        engine.force(false);
+        assert_eq!(
+            engine.decide(&inputs(&without, false, true, false)).reason,
+            Reason::Pin
+        );
+        assert_eq!(
+            engine.decide(&inputs(&without, false, false, false)).reason,
+            Reason::Manual
+        );
+    }
+
+    #[test]
+    fn intellihide_only_counts_windows_over_the_bar() {
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let bar = [rect(0, 0, 1920, 30)];
+        let mut outputs = outputs(Some("DP-1"), &[("DP-1", true)]);
+        // A floating dialog in the middle of the screen
+        outputs
+            .areas
+            .insert("DP-1".to_string(), vec![rect(700, 400, 500, 300)]);
+        let mut engine = VisibilityEngine::default();
+        let mut inputs = inputs(&outputs, false, false, false);
+        assert_eq!(engine.decide(&inputs).reason, Reason::Windows);
+        inputs.bar_areas = Some(&bar);
+        let decision = engine.decide(&inputs);
+        assert_eq!(
+            (decision.visible, decision.reason),
+            (true, Reason::Uncovered)
+        );
+
+        // Dragged up to the bar
+        outputs
+            .areas
+            .insert("DP-1".to_string(), vec![rect(700, 20, 500, 300)]);
+        let inputs = Inputs {
+            bar_areas: Some(&bar),
+            ..self::inputs(&outputs, false, false, false)
+        };
+        assert_eq!(engine.decide(&inputs).reason, Reason::Windows);
+
+        // Without geometry for the monitor, any window counts
+        outputs.areas.clear();
+        let inputs = Inputs {
+            bar_areas: Some(&bar),
+            ..self::inputs(&outputs, false, false, false)
+        };
+        assert_eq!(engine.decide(&inputs).reason, Reason::Windows);
+    }
+
+    /// A xorshift generator, so property tests need no extra crate and fail the same way every run
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn chance(&mut self, one_in: u64) -> bool {
+            self.next().is_multiple_of(one_in)
+        }
+    }
+
+    /// Random sequences of inputs and commands, checking what must hold after every step
+    #[test]
+    fn properties_hold_for_random_sequences() {
+        let monitors = ["DP-1", "DP-2"];
+        for seed in 1..=200u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let mut engine = VisibilityEngine::default();
+            let mut previous_auto = true;
+            let output = rng.chance(2).then(|| monitors[rng.next() as usize % 2]);
+            for step in 0..200 {
+                let outputs = outputs(
+                    Some(monitors[rng.next() as usize % 2]),
+                    &[("DP-1", rng.chance(2)), ("DP-2", rng.chance(2))],
+                );
+                let inputs = Inputs {
+                    at_edge: rng.chance(4),
+                    output,
+                    outputs: &outputs,
+                    pinned: rng.chance(8),
+                    peeking: rng.chance(8),
+                    bar_areas: None,
+                };
+                if rng.chance(5) {
+                    engine.force(rng.chance(2));
+                }
+                let forced_before = engine.forced();
+                let auto = automatic(&inputs);
+                let decision = engine.decide(&inputs);
+                let context =
+                    format!("seed {seed}, step {step}, {inputs:?}, forced {forced_before:?}");
+
+                // Showing is always backed by a reason to show, hiding by windows or a manual hide
+                match decision.reason {
+                    Reason::Cursor
+                    | Reason::EmptyWorkspace
+                    | Reason::Uncovered
+                    | Reason::Pin
+                    | Reason::Peek => {
+                        assert!(decision.visible, "{context}")
+                    }
+                    Reason::Windows => assert!(!decision.visible, "{context}"),
+                    Reason::Manual => {
+                        assert_eq!(Some(decision.visible), forced_before, "{context}")
+                    }
+                }
+                if inputs.pinned || inputs.peeking {
+                    assert!(decision.visible, "{context}");
+                }
+                // An override never survives a change of the automatic decision
+                if auto.visible != previous_auto {
+                    assert_eq!(engine.forced(), None, "{context}");
+                }
+                if engine.forced().is_none() && !inputs.pinned && !inputs.peeking {
+                    assert_eq!(decision, auto, "{context}");
+                }
+                // Deciding again on the same inputs changes nothing
+                assert_eq!(engine.clone().decide(&inputs), decision, "{context}");
+                previous_auto = auto.visible;
+            }
+        }
+    }
+
+    #[test]
+    fn delayed_follows_an_input_that_holds() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let delay = Duration::from_millis(100);
+        let mut flag = Delayed::new(false);
+        assert!(!flag.update(true, delay, at(0)));
+        assert_eq!(flag.deadline(), Some(at(100)));
+        assert!(!flag.update(true, delay, at(99)));
+        assert!(flag.update(true, delay, at(100)));
+        assert_eq!(flag.deadline(), None);
+
+        // Flickering back before the delay restarts it
+        assert!(flag.update(false, delay, at(150)));
+        assert!(flag.update(true, delay, at(200)));
+        assert!(flag.update(false, delay, at(250)));
+        assert!(flag.update(false, delay, at(349)));
+        assert!(!flag.update(false, delay, at(350)));
+
+        // Without a delay the input is followed right away
+        assert!(flag.update(true, Duration::ZERO, at(400)));
+        assert_eq!(flag.deadline(), None);
+    }
+}
@@ -0,0 +1,527 @@
+use crate::{
+    bar::expand_home,
+    config::{Edge, Signal, SignalMode, WaybarConfig},
+};
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// What the Waybar config says about one of its bars
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaybarBar {
+    /// The screen edge of the bar, `top` when unset
+    pub position: Edge,
+    /// `height` for horizontal bars, `width` for vertical ones. Unset, Waybar sizes the bar to its content.
+    pub size: Option<i32>,
+    /// The margin between the bar and its screen edge
+    pub margin: i32,
+    /// The `output` filter, `None` when the bar is shown on every monitor
+    pub outputs: Option<Vec<String>>,
+    /// The `on-sigusr1` and `on-sigusr2` actions, when set
+    pub on_sigusr1: Option<String>,
+    /// See `on_sigusr1`
+    pub on_sigusr2: Option<String>,
+}
+
+impl WaybarBar {
+    /// How far from the edge the bar reaches, including its margin
+    pub fn extent(&self) -> Option<i32> {
+        self.size.map(|size| size + self.margin)
+    }
+
+    /// What Waybar does with this bar when it receives a signal, `None` if it does not react to it
+    pub fn action(&self, signal: Signal) -> Option<&str> {
+        // Waybar defaults to toggling on SIGUSR1 and reloading on SIGUSR2
+        match signal.0 {
+            libc::SIGUSR1 => Some(self.on_sigusr1.as_deref().unwrap_or("toggle")),
+            libc::SIGUSR2 => Some(self.on_sigusr2.as_deref().unwrap_or("reload")),
+            _ => None,
+        }
+    }
+
+    /// The only monitor this bar is shown on, if it is restricted to a single one
+    pub fn single_output(&self) -> Option<&str> {
+        match self.outputs.as_deref() {
+            Some([only]) if !only.starts_with('!') && only != "*" => Some(only),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a Waybar `output` filter lets a bar show on the given monitor.
+/// Waybar accepts negations (`!DP-1`) and a `*` wildcard, the first match wins.
+pub fn shown_on(outputs: Option<&[String]>, output: &str) -> bool {
+    let Some(outputs) = outputs else {
+        return true;
+    };
+    for entry in outputs {
+        match entry.strip_prefix('!') {
+            Some(name) if name == output || name == "*" => return false,
+            None if entry == output || entry == "*" => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Resolves `SignalMode::Auto` for the bars of one Waybar instance
+pub fn signal_mode(bars: &[WaybarBar], waybar: &WaybarConfig) -> SignalMode {
+    if waybar.signal_mode != SignalMode::Auto {
+        return waybar.signal_mode;
+    }
+    let all = |signal: Signal, action: &str| {
+        !bars.is_empty() && bars.iter().all(|bar| bar.action(signal) == Some(action))
+    };
+    if !all(waybar.show_signal, "show") && all(waybar.hide_signal, "toggle") {
+        SignalMode::Toggle
+    } else {
+        SignalMode::ShowHide
+    }
+}
+
+/// A problem in the Waybar config that keeps the daemon from driving the bar reliably
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    /// Index of the bar in the Waybar config
+    pub bar: usize,
+    /// Whether the bar will not work at all, rather than flicker or risk desyncing
+    pub fatal: bool,
+    /// What is wrong
+    pub problem: String,
+    /// What to change in the Waybar config
+    pub fix: String,
+}
+
+/// Checks that the `on-sigusr1`/`on-sigusr2` actions of every bar match the signals the daemon sends
+pub fn lint(bars: &[WaybarBar], waybar: &WaybarConfig, mode: SignalMode) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let key = |signal: Signal| match signal.0 {
+        libc::SIGUSR1 => "on-sigusr1",
+        _ => "on-sigusr2",
+    };
+    for (idx, bar) in bars.iter().enumerate() {
+        // Toggle mode only needs `hide_signal` to toggle the bar
+        let toggles = bar.action(waybar.hide_signal) == Some("toggle");
+        let mut check = |signal: Signal, wanted: &str, setting: &str| {
+            let Some(action) = bar.action(signal) else {
+                findings.push(Finding {
+                    bar: idx,
+                    fatal: true,
+                    problem: format!(
+                        "Waybar only changes its visibility on SIGUSR1 and SIGUSR2, but `waybar.{setting}` is {signal}"
+                    ),
+                    fix: format!("set `waybar.{setting}` to \"SIGUSR1\" or \"SIGUSR2\""),
+                });
+                return;
+            };
+            if action == wanted {
+                return;
+            }
+            let mut fix = format!("set \"{}\": \"{wanted}\" in the bar", key(signal));
+            let (fatal, problem) = match (wanted, action) {
+                ("hide", "toggle") => (
+                    false,
+                    format!(
+                        "{signal} toggles the bar instead of hiding it, so the daemon and the bar can get out of sync"
+                    ),
+                ),
+                ("show", "reload") => (
+                    false,
+                    format!(
+                        "{signal} reloads Waybar instead of showing the bar, which flickers and rereads its config every time"
+                    ),
+                ),
+                _ => (
+                    true,
+                    format!(
+                        "{signal} makes the bar {action}, but the daemon sends it to {wanted} the bar"
+                    ),
+                ),
+            };
+            if !fatal && toggles && mode == SignalMode::ShowHide {
+                fix.push_str(&format!(
+                    ", or set `waybar.signal_mode` to \"auto\" to toggle it with {} instead",
+                    waybar.hide_signal
+                ));
+            }
+            findings.push(Finding {
+                bar: idx,
+                fatal,
+                problem,
+                fix,
+            });
+        };
+        match mode {
+            SignalMode::Toggle => check(waybar.hide_signal, "toggle", "hide_signal"),
+            _ => {
+                check(waybar.hide_signal, "hide", "hide_signal");
+                check(waybar.show_signal, "show", "show_signal");
+            }
+        }
+    }
+    findings
+}
+
+/// Finds the config file used by a running Waybar process, given its `--config` argument if any
+pub fn locate_config(pid: i32, config_arg: Option<&Path>) -> Option<PathBuf> {
+    if let Some(path) = config_arg {
+        // Relative paths are resolved by Waybar against its own working directory
+        if path.is_relative() {
+            let cwd = fs::read_link(format!("/proc/{pid}/cwd")).ok()?;
+            return Some(cwd.join(path));
+        }
+        return Some(path.to_path_buf());
+    }
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")));
+    let dirs = config_home
+        .into_iter()
+        .chain([PathBuf::from("/etc/xdg")])
+        .map(|dir| dir.join("waybar"));
+    dirs.flat_map(|dir| [dir.join("config.jsonc"), dir.join("config")])
+        .find(|path| path.is_file())
+}
+
+/// Reads every bar of a Waybar config, following its `include` files
+pub fn read_config(path: &Path) -> Option<Vec<WaybarBar>> {
+    let objects = match load(path, 0)? {
+        Value::Array(bars) => bars,
+        bar => vec![bar],
+    };
+    Some(
+        objects
+            .iter()
+            .filter_map(Value::as_object)
+            .map(|bar| parse_bar(bar, path))
+            .collect(),
+    )
+}
+
+/// Includes can nest, but not forever
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+fn load(path: &Path, depth: usize) -> Option<Value> {
+    let source = fs::read_to_string(path).ok()?;
+    let value: Value = serde_json::from_str(&strip_jsonc(&source)).ok()?;
+    if depth >= MAX_INCLUDE_DEPTH {
+        return Some(value);
+    }
+    Some(match value {
+        Value::Array(bars) => Value::Array(
+            bars.into_iter()
+                .map(|bar| resolve_includes(bar, path, depth))
+                .collect(),
+        ),
+        bar => resolve_includes(bar, path, depth),
+    })
+}
+
+/// Merges the objects from `include` into a bar. Keys set by the bar itself win.
+fn resolve_includes(mut bar: Value, path: &Path, depth: usize) -> Value {
+    let Some(object) = bar.as_object_mut() else {
+        return bar;
+    };
+    let includes: Vec<String> = match object.get("include") {
+        Some(Value::String(file)) => vec![file.clone()],
+        Some(Value::Array(files)) => files
+            .iter()
+            .filter_map(|f| f.as_str().map(str::to_string))
+            .collect(),
+        _ => return bar,
+    };
+    let base = path.parent().unwrap_or(Path::new("/"));
+    for file in includes {
+        let file = base.join(expand_home(&file));
+        let Some(Value::Object(included)) = load(&file, depth + 1) else {
+            continue;
+        };
+        for (key, value) in included {
+            object.entry(key).or_insert(value);
+        }
+    }
+    bar
+}
+
+fn parse_bar(bar: &serde_json::Map<String, Value>, path: &Path) -> WaybarBar {
+    let int = |key: &str| bar.get(key).and_then(Value::as_i64).map(|v| v as i32);
+
+    let position = match bar.get("position").and_then(Value::as_str) {
+        Some("bottom") => Edge::Bottom,
+        Some("left") => Edge::Left,
+        Some("right") => Edge::Right,
+        Some("top") | None => Edge::Top,
+        Some(other) => {
+            eprintln!(
+                "waybar_auto_hide: {}: unknown bar position `{other}`, assuming top",
+                path.display()
+            );
+            Edge::Top
+        }
+    };
+    let size = match position {
+        Edge::Top | Edge::Bottom => int("height"),
+        Edge::Left | Edge::Right => int("width"),
+    };
+
+    // `margin` is a CSS style shorthand, `margin-<side>` wins over it
+    let [top, right, bottom, left] = bar
+        .get("margin")
+        .and_then(|m| match m {
+            Value::String(s) => parse_margin(s),
+            Value::Number(n) => n.as_i64().map(|n| [n as i32; 4]),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let margin = match position {
+        Edge::Top => int("margin-top").unwrap_or(top),
+        Edge::Bottom => int("margin-bottom").unwrap_or(bottom),
+        Edge::Left => int("margin-left").unwrap_or(left),
+        Edge::Right => int("margin-right").unwrap_or(right),
+    };
+
+    let outputs = match bar.get("output") {
+        Some(Value::String(output)) => Some(vec![output.clone()]),
+        Some(Value::Array(outputs)) => Some(
+            outputs
+                .iter()
+                .filter_map(|o| o.as_str().map(str::to_string))
+                .collect(),
+        ),
+        _ => None,
+    };
+
+    let string = |key: &str| bar.get(key).and_then(Value::as_str).map(str::to_string);
+    WaybarBar {
+        position,
+        size,
+        margin,
+        outputs,
+        on_sigusr1: string("on-sigusr1"),
+        on_sigusr2: string("on-sigusr2"),
+    }
+}
+
+/// Parses `"5"`, `"5 10"`, `"5 10 0"` or `"5 10 0 10"` into top, right, bottom, left
+fn parse_margin(margin: &str) -> Option<[i32; 4]> {
+    let values: Vec<i32> = margin
+        .split_whitespace()
+        .map(|v| v.trim_end_matches("px").parse().ok())
+        .collect::<Option<_>>()?;
+    match values[..] {
+        [all] => Some([all; 4]),
+        [vertical, horizontal] => Some([vertical, horizontal, vertical, horizontal]),
+        [top, horizontal, bottom] => Some([top, horizontal, bottom, horizontal]),
+        [top, right, bottom, left] => Some([top, right, bottom, left]),
+        _ => None,
+    }
+}
+
+/// Turns JSONC into JSON by dropping comments and trailing commas, leaving strings untouched
+fn strip_jsonc(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => while chars.next_if(|&c| c != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    remove_trailing_commas(&out)
+}
+
+fn remove_trailing_commas(json: &str) -> String {
+    let mut out = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut last_comma = None;
+    for c in json.chars() {
+        if in_string {
+            match (escaped, c) {
+                (true, _) => escaped = false,
+                (false, '\\') => escaped = true,
+                (false, '"') => in_string = false,
+                _ => {}
+            }
+        } else if c == '}' || c == ']' {
+            // A comma directly followed by a closing bracket is dropped
+            if let Some(at) = last_comma.take() {
+                out.remove(at);
+            }
+        } else if c == ',' {
+            last_comma = Some(out.len());
+        } else if !c.is_whitespace() {
+            last_comma = None;
+            in_string = c == '"';
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_are_stripped_outside_strings() {
+        let source = r#"{
+            // a line comment
+            "format": "{icon} // not a comment", /* a block
+            comment */ "url": "http://example.com/*x*/",
+            "quote": "say \"hi\" // still text"
+        }"#;
+        let value: Value = serde_json::from_str(&strip_jsonc(source)).unwrap();
+        assert_eq!(value["format"], "{icon} // not a comment");
+        assert_eq!(value["url"], "http://example.com/*x*/");
+        assert_eq!(value["quote"], "say \"hi\" // still text");
+    }
+
+    #[test]
+    fn trailing_commas_are_dropped_at_any_depth() {
+        let json = remove_trailing_commas(r#"[{"a": [1, [2, 3,], ], "b": "x,]",}, ]"#);
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value[0]["a"], serde_json::json!([1, [2, 3]]));
+        // Commas inside strings are text
+        assert_eq!(value[0]["b"], "x,]");
+        assert_eq!(
+            remove_trailing_commas(r#"{"a": "b\",", }"#),
+            r#"{"a": "b\"," }"#
+        );
+    }
+
+    #[test]
+    fn margins_follow_css_shorthands() {
+        assert_eq!(parse_margin("5"), Some([5; 4]));
+        assert_eq!(parse_margin("5 10"), Some([5, 10, 5, 10]));
+        assert_eq!(parse_margin("5px 10px 0"), Some([5, 10, 0, 10]));
+        assert_eq!(parse_margin("1 2 3 4"), Some([1, 2, 3, 4]));
+        assert_eq!(parse_margin("1 2 3 4 5"), None);
+        assert_eq!(parse_margin("auto"), None);
+        assert_eq!(parse_margin(""), None);
+    }
+
+    fn bar(on_sigusr1: Option<&str>, on_sigusr2: Option<&str>) -> WaybarBar {
+        WaybarBar {
+            position: Edge::Top,
+            size: None,
+            margin: 0,
+            outputs: None,
+            on_sigusr1: on_sigusr1.map(str::to_string),
+            on_sigusr2: on_sigusr2.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn toggle_mode_is_opt_in() {
+        let defaults = [bar(None, None)];
+        let mut waybar = WaybarConfig::default();
+        assert_eq!(signal_mode(&defaults, &waybar), SignalMode::ShowHide);
+        let findings = lint(&defaults, &waybar, SignalMode::ShowHide);
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(|f| !f.fatal));
+        assert!(
+            findings
+                .iter()
+                .all(|f| f.fix.contains("`waybar.signal_mode` to \"auto\"")),
+            "{findings:?}"
+        );
+
+        waybar.signal_mode = SignalMode::Auto;
+        assert_eq!(signal_mode(&defaults, &waybar), SignalMode::Toggle);
+        assert!(lint(&defaults, &waybar, SignalMode::Toggle).is_empty());
+        let configured = [bar(Some("hide"), Some("show"))];
+        assert_eq!(signal_mode(&configured, &waybar), SignalMode::ShowHide);
+
+        // Toggling cannot work once SIGUSR1 hides
+        let findings = lint(&[bar(Some("hide"), None)], &waybar, SignalMode::ShowHide);
+        assert_eq!(findings.len(), 1);
+        assert!(!findings[0].fix.contains("signal_mode"));
+    }
+
+    #[test]
+    fn output_filters() {
+        let filter = |outputs: &[&str]| outputs.iter().map(|o| o.to_string()).collect::<Vec<_>>();
+        assert!(shown_on(None, "DP-1"));
+        let only = filter(&["DP-1"]);
+        assert!(shown_on(Some(&only), "DP-1"));
+        assert!(!shown_on(Some(&only), "DP-2"));
+        // The first match wins
+        let all_but = filter(&["!DP-1", "*"]);
+        assert!(!shown_on(Some(&all_but), "DP-1"));
+        assert!(shown_on(Some(&all_but), "HDMI-A-1"));
+        let none = filter(&["!*", "DP-1"]);
+        assert!(!shown_on(Some(&none), "DP-1"));
+    }
+
+    #[test]
+    fn includes_fill_in_what_the_bar_leaves_unset() {
+        let dir =
+            std::env::temp_dir().join(format!("waybar_auto_hide-waybar-{}", std::process::id()));
+        fs::create_dir_all(dir.join("modules")).unwrap();
+        fs::write(
+            dir.join("config.jsonc"),
+            r#"[
+                // The bar itself wins over its includes
+                {"include": ["modules/a.jsonc", "modules/b.jsonc"], "height": 30,},
+                {"position": "left", "width": 40, "include": "modules/b.jsonc"}
+            ]"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("modules/a.jsonc"),
+            r#"{"height": 99, "position": "bottom", "margin": "4 8", "include": "c.jsonc"}"#,
+        )
+        .unwrap();
+        fs::write(dir.join("modules/c.jsonc"), r#"{"output": ["!DP-2", "*"]}"#).unwrap();
+        fs::write(
+            dir.join("modules/b.jsonc"),
+            r#"{"position": "right", "margin-left": 6, "on-sigusr1": "hide"}"#,
+        )
+        .unwrap();
+
+        let bars = read_config(&dir.join("config.jsonc")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(bars.len(), 2);
+        // The first include wins over the next one, and includes are relative to their file
+        assert_eq!(bars[0].position, Edge::Bottom);
+        assert_eq!(bars[0].size, Some(30));
+        assert_eq!(bars[0].margin, 4);
+        assert_eq!(bars[0].extent(), Some(34));
+        assert_eq!(
+            bars[0].outputs,
+            Some(vec!["!DP-2".to_string(), "*".to_string()])
+        );
+        assert_eq!(bars[0].on_sigusr1.as_deref(), Some("hide"));
+        assert_eq!(bars[1].position, Edge::Left);
+        assert_eq!(bars[1].margin, 6);
+        assert_eq!(bars[1].outputs, None);
+    }
+}
@@ -0,0 +1,1050 @@
+use crate::{
+    Event, MonitorLayout, Outputs,
+    compositor::{self, Compositor, CursorPos, OutputsSender},
+    monitor::{Monitor, WorkspaceRef},
+};
+use std::{
+    collections::HashMap,
+    io::{self, BufReader, Read, Write},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::net::UnixStream,
+    },
+    path::PathBuf,
+    sync::{Arc, atomic::AtomicBool, mpsc::Sender},
+    time::Duration,
+};
+
+/// `wl_display` always has the first id
+pub(crate) const DISPLAY: u32 = 1;
+
+/// Compositors without an IPC of their own (River, labwc, Wayfire...), through the
+/// wlr-foreign-toplevel-management and, where available, ext-workspace protocols
+pub struct Wayland {
+    socket: PathBuf,
+}
+
+impl Wayland {
+    /// Finds the compositor's socket through the environment, `None` when not running under it
+    pub fn from_env() -> Option<Wayland> {
+        Some(Wayland { socket: socket()? })
+    }
+
+    /// Connects and waits until the initial state of every object has arrived
+    fn session(&self) -> Option<Session> {
+        let mut session = Session::new(UnixStream::connect(&self.socket).ok()?).ok()?;
+        session.start().ok()?;
+        if !session.has_toplevels {
+            eprintln!(
+                "waybar_auto_hide: the compositor does not support wlr-foreign-toplevel-management"
+            );
+            return None;
+        }
+        Some(session)
+    }
+
+    /// Dispatches the events of the session, which holds every output and toplevel
+    fn follow_events(mut session: Session, tx: &Sender<Event>, monitors: &MonitorLayout) -> bool {
+        *monitors.write().unwrap() = session.monitors();
+        let mut sender = OutputsSender::new(tx);
+        if !sender.send(session.outputs()) {
+            return false;
+        }
+        while let Ok(message) = session.conn.read() {
+            match session.dispatch(message) {
+                Ok(Change::Monitors) => *monitors.write().unwrap() = session.monitors(),
+                Ok(Change::Windows) => {}
+                Ok(Change::Nothing) => continue,
+                Err(_) => return true,
+            }
+            if !sender.send(session.outputs()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Compositor for Wayland {
+    fn name(&self) -> &'static str {
+        "wlr-foreign-toplevel"
+    }
+
+    /// Wayland clients only see the pointer over their own surfaces
+    fn has_cursor(&self) -> bool {
+        false
+    }
+
+    fn cursor_pos(&self) -> Option<CursorPos> {
+        None
+    }
+
+    fn monitors(&self) -> Option<Vec<Monitor>> {
+        Some(self.session()?.monitors())
+    }
+
+    fn outputs(&self) -> Option<Outputs> {
+        Some(self.session()?.outputs())
+    }
+
+    /// Workspace switches are not told apart from other changes, so they never peek.
+    /// Window geometry is not reported either.
+    fn listen(&self, tx: Sender<Event>, monitors: MonitorLayout, _geometry: Arc<AtomicBool>) {
+        compositor::reconnecting(
+            self.name(),
+            || self.session(),
+            |session| Self::follow_events(session, &tx, &monitors),
+        );
+    }
+}
+
+/// The socket of the session's compositor, from `$WAYLAND_DISPLAY`
+pub(crate) fn socket() -> Option<PathBuf> {
+    let display = PathBuf::from(std::env::var_os("WAYLAND_DISPLAY").filter(|v| !v.is_empty())?);
+    if display.is_absolute() {
+        return Some(display);
+    }
+    Some(PathBuf::from(std::env::var_os("XDG_RUNTIME_DIR")?).join(display))
+}
+
+/// A message from the compositor
+#[derive(Debug)]
+pub(crate) struct Message {
+    pub(crate) object: u32,
+    pub(crate) opcode: u16,
+    pub(crate) body: Vec<u8>,
+}
+
+/// An argument of a request
+pub(crate) enum Arg<'a> {
+    Uint(u32),
+    Int(i32),
+    Str(&'a str),
+    /// `new_id` and `object` are both plain ids on the wire
+    Id(u32),
+}
+
+/// The wire protocol: 32-bit words in native byte order, a header with the object id,
+/// then the message size and opcode packed in one word
+pub(crate) struct Connection {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u32,
+}
+
+impl Connection {
+    pub(crate) fn new(stream: UnixStream) -> io::Result<Connection> {
+        Ok(Connection {
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+            next_id: DISPLAY + 1,
+        })
+    }
+
+    /// Ids are never reused, so objects deleted by the server cannot be confused with new ones
+    pub(crate) fn new_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    pub(crate) fn send(&mut self, object: u32, opcode: u16, args: &[Arg]) -> io::Result<()> {
+        self.writer.write_all(&encode(object, opcode, args))
+    }
+
+    /// Sends a request with a file descriptor, which travels beside the message rather than in it
+    pub(crate) fn send_fd(
+        &mut self,
+        object: u32,
+        opcode: u16,
+        args: &[Arg],
+        fd: RawFd,
+    ) -> io::Result<()> {
+        let message = encode(object, opcode, args);
+        let mut iov = libc::iovec {
+            iov_base: message.as_ptr() as *mut libc::c_void,
+            iov_len: message.len(),
+        };
+        let space = unsafe { libc::CMSG_SPACE(size_of::<RawFd>() as u32) } as usize;
+        let mut control = vec![0u8; space];
+        let mut header: libc::msghdr = unsafe { std::mem::zeroed() };
+        header.msg_iov = &mut iov;
+        header.msg_iovlen = 1;
+        header.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        header.msg_controllen = space as _;
+        let sent = unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&header);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<RawFd>() as u32) as _;
+            std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+            libc::sendmsg(self.writer.as_raw_fd(), &header, libc::MSG_NOSIGNAL)
+        };
+        match sent {
+            -1 => Err(io::Error::last_os_error()),
+            n if n as usize != message.len() => Err(invalid("request cut short")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Waits up to `timeout` for a message, true when one can be read without blocking
+    pub(crate) fn wait(&self, timeout: Duration) -> io::Result<bool> {
+        if !self.reader.buffer().is_empty() {
+            return Ok(true);
+        }
+        let mut fd = libc::pollfd {
+            fd: self.reader.get_ref().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as i32) } {
+            -1 => Err(io::Error::last_os_error()),
+            ready => Ok(ready > 0),
+        }
+    }
+
+    pub(crate) fn read(&mut self) -> io::Result<Message> {
+        let mut header = [0u8; 8];
+        self.reader.read_exact(&mut header)?;
+        let object = u32::from_ne_bytes(header[..4].try_into().unwrap());
+        let word = u32::from_ne_bytes(header[4..].try_into().unwrap());
+        let size = (word >> 16) as usize;
+        if size < 8 {
+            return Err(invalid("message shorter than its header"));
+        }
+        let mut body = vec![0u8; size - 8];
+        self.reader.read_exact(&mut body)?;
+        Ok(Message {
+            object,
+            opcode: word as u16,
+            body,
+        })
+    }
+}
+
+/// Lays out a request: the object id, the size and opcode packed in one word, then the arguments
+fn encode(object: u32, opcode: u16, args: &[Arg]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for arg in args {
+        match arg {
+            Arg::Uint(value) | Arg::Id(value) => body.extend_from_slice(&value.to_ne_bytes()),
+            Arg::Int(value) => body.extend_from_slice(&value.to_ne_bytes()),
+            Arg::Str(text) => {
+                // Length includes the NUL terminator, the string is then padded to 32 bits
+                body.extend_from_slice(&(text.len() as u32 + 1).to_ne_bytes());
+                body.extend_from_slice(text.as_bytes());
+                body.push(0);
+                body.resize(body.len().next_multiple_of(4), 0);
+            }
+        }
+    }
+    let mut message = Vec::with_capacity(8 + body.len());
+    message.extend_from_slice(&object.to_ne_bytes());
+    message.extend_from_slice(&((((8 + body.len()) as u32) << 16) | opcode as u32).to_ne_bytes());
+    message.extend_from_slice(&body);
+    message
+}
+
+pub(crate) fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads the arguments of an event in order
+pub(crate) struct Args<'a>(pub(crate) &'a [u8]);
+
+impl Args<'_> {
+    pub(crate) fn uint(&mut self) -> io::Result<u32> {
+        let (word, rest) = self
+            .0
+            .split_first_chunk::<4>()
+            .ok_or_else(|| invalid("truncated event"))?;
+        self.0 = rest;
+        Ok(u32::from_ne_bytes(*word))
+    }
+
+    pub(crate) fn int(&mut self) -> io::Result<i32> {
+        Ok(self.uint()? as i32)
+    }
+
+    pub(crate) fn array(&mut self) -> io::Result<&[u8]> {
+        let len = self.uint()? as usize;
+        let padded = len.next_multiple_of(4);
+        if self.0.len() < padded {
+            return Err(invalid("truncated event"));
+        }
+        let (data, rest) = self.0.split_at(padded);
+        self.0 = rest;
+        Ok(&data[..len])
+    }
+
+    pub(crate) fn string(&mut self) -> io::Result<String> {
+        let data = self.array()?;
+        // Drops the NUL terminator
+        let text = data.strip_suffix(&[0]).unwrap_or(data);
+        Ok(String::from_utf8_lossy(text).into_owned())
+    }
+}
+
+/// What the proxy behind an id is
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Object {
+    Registry,
+    Callback,
+    Output,
+    XdgOutputManager,
+    /// The `zxdg_output_v1` of a `wl_output`
+    XdgOutput(u32),
+    ToplevelManager,
+    Toplevel,
+    WorkspaceManager,
+    WorkspaceGroup,
+    Workspace,
+}
+
+#[derive(Debug, Default)]
+struct Output {
+    name: String,
+    x: i32,
+    y: i32,
+    /// Size of the current mode, before scaling and rotation
+    width: i32,
+    height: i32,
+    scale: i32,
+    transform: i32,
+    /// Position and size in the layout space, from xdg-output
+    logical: Option<(i32, i32, i32, i32)>,
+}
+
+#[derive(Debug, Default)]
+struct Toplevel {
+    /// `wl_output` ids the toplevel is shown on
+    outputs: Vec<u32>,
+    /// Outputs that switched to another workspace since the toplevel last entered them or was
+    /// activated, where it is taken to be on a hidden workspace
+    hidden_on: Vec<u32>,
+    minimized: bool,
+    activated: bool,
+}
+
+/// An ext-workspace group, usually one per output
+#[derive(Debug, Default)]
+struct Group {
+    outputs: Vec<u32>,
+    workspaces: Vec<u32>,
+}
+
+#[derive(Debug, Default)]
+struct Workspace {
+    name: String,
+    active: bool,
+}
+
+/// What an event changed
+#[derive(Debug, PartialEq)]
+enum Change {
+    Nothing,
+    Windows,
+    Monitors,
+}
+
+/// The globals the daemon binds and the state the compositor told about them
+struct Session {
+    conn: Connection,
+    objects: HashMap<u32, Object>,
+    /// Registry name of every bound `wl_output`, to notice when it goes away
+    output_globals: HashMap<u32, u32>,
+    xdg_output_manager: Option<u32>,
+    has_toplevels: bool,
+    outputs: HashMap<u32, Output>,
+    toplevels: HashMap<u32, Toplevel>,
+    groups: HashMap<u32, Group>,
+    workspaces: HashMap<u32, Workspace>,
+    /// The active workspace of each output, as of the last `done` of the workspace manager
+    active: HashMap<u32, u32>,
+}
+
+impl Session {
+    fn new(stream: UnixStream) -> io::Result<Session> {
+        Ok(Session {
+            conn: Connection::new(stream)?,
+            objects: HashMap::new(),
+            output_globals: HashMap::new(),
+            xdg_output_manager: None,
+            has_toplevels: false,
+            outputs: HashMap::new(),
+            toplevels: HashMap::new(),
+            groups: HashMap::new(),
+            workspaces: HashMap::new(),
+            active: HashMap::new(),
+        })
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        let registry = self.conn.new_id();
+        self.conn.send(DISPLAY, 1, &[Arg::Id(registry)])?;
+        self.objects.insert(registry, Object::Registry);
+        // Globals, then the initial state of what was bound, then of the xdg outputs created from it
+        for _ in 0..3 {
+            self.roundtrip()?;
+        }
+        Ok(())
+    }
+
+    /// Handles events until the compositor has processed every request sent so far
+    fn roundtrip(&mut self) -> io::Result<()> {
+        let callback = self.conn.new_id();
+        self.conn.send(DISPLAY, 0, &[Arg::Id(callback)])?;
+        self.objects.insert(callback, Object::Callback);
+        loop {
+            let message = self.conn.read()?;
+            if message.object == callback {
+                self.objects.remove(&callback);
+                return Ok(());
+            }
+            self.dispatch(message)?;
+        }
+    }
+
+    fn bind(&mut self, name: u32, interface: &str, version: u32, kind: Object) -> io::Result<u32> {
+        let id = self.conn.new_id();
+        let registry = self
+            .objects
+            .iter()
+            .find(|(_, kind)| **kind == Object::Registry)
+            .map(|(id, _)| *id)
+            .ok_or_else(|| invalid("no registry"))?;
+        self.conn.send(
+            registry,
+            0,
+            &[
+                Arg::Uint(name),
+                Arg::Str(interface),
+                Arg::Uint(version),
+                Arg::Id(id),
+            ],
+        )?;
+        self.objects.insert(id, kind);
+        Ok(id)
+    }
+
+    fn get_xdg_output(&mut self, manager: u32, output: u32) -> io::Result<()> {
+        let id = self.conn.new_id();
+        self.conn
+            .send(manager, 1, &[Arg::Id(id), Arg::Id(output)])?;
+        self.objects.insert(id, Object::XdgOutput(output));
+        Ok(())
+    }
+
+    fn dispatch(&mut self, message: Message) -> io::Result<Change> {
+        let mut args = Args(&message.body);
+        if message.object == DISPLAY {
+            return match message.opcode {
+                0 => {
+                    let (_, _) = (args.uint()?, args.uint()?);
+                    Err(io::Error::other(format!(
+                        "Wayland error: {}",
+                        args.string()?
+                    )))
+                }
+                1 => {
+                    self.objects.remove(&args.uint()?);
+                    Ok(Change::Nothing)
+                }
+                _ => Ok(Change::Nothing),
+            };
+        }
+        let Some(&kind) = self.objects.get(&message.object) else {
+            return Ok(Change::Nothing);
+        };
+        let id = message.object;
+        let change = match (kind, message.opcode) {
+            (Object::Registry, 0) => {
+                let (name, interface, version) = (args.uint()?, args.string()?, args.uint()?);
+                match interface.as_str() {
+                    "wl_output" => {
+                        // Version 4 adds the output name
+                        let output = self.bind(name, &interface, version.min(4), Object::Output)?;
+                        self.outputs.insert(output, Output::default());
+                        self.output_globals.insert(name, output);
+                        if let Some(manager) = self.xdg_output_manager {
+                            self.get_xdg_output(manager, output)?;
+                        }
+                        Change::Monitors
+                    }
+                    "zxdg_output_manager_v1" => {
+                        let manager =
+                            self.bind(name, &interface, version.min(3), Object::XdgOutputManager)?;
+                        self.xdg_output_manager = Some(manager);
+                        let outputs: Vec<u32> = self.outputs.keys().copied().collect();
+                        for output in outputs {
+                            self.get_xdg_output(manager, output)?;
+                        }
+                        Change::Nothing
+                    }
+                    "zwlr_foreign_toplevel_manager_v1" => {
+                        self.bind(name, &interface, version.min(3), Object::ToplevelManager)?;
+                        self.has_toplevels = true;
+                        Change::Nothing
+                    }
+                    "ext_workspace_manager_v1" => {
+                        self.bind(name, &interface, 1, Object::WorkspaceManager)?;
+                        Change::Nothing
+                    }
+                    _ => Change::Nothing,
+                }
+            }
+            (Object::Registry, 1) => match self.output_globals.remove(&args.uint()?) {
+                Some(output) => {
+                    self.outputs.remove(&output);
+                    for toplevel in self.toplevels.values_mut() {
+                        toplevel.outputs.retain(|&o| o != output);
+                    }
+                    Change::Monitors
+                }
+                None => Change::Nothing,
+            },
+            (Object::Output, opcode) => {
+                let output = self.outputs.entry(id).or_default();
+                match opcode {
+                    0 => {
+                        output.x = args.int()?;
+                        output.y = args.int()?;
+                        let _physical_size = (args.int()?, args.int()?, args.int()?);
+                        let _make_model = (args.string()?, args.string()?);
+                        output.transform = args.int()?;
+                        Change::Nothing
+                    }
+                    1 => {
+                        // Only the current mode matters
+                        if args.uint()? & 1 != 0 {
+                            output.width = args.int()?;
+                            output.height = args.int()?;
+                        }
+                        Change::Nothing
+                    }
+                    2 => Change::Monitors,
+                    3 => {
+                        output.scale = args.int()?;
+                        Change::Nothing
+                    }
+                    4 => {
+                        output.name = args.string()?;
+                        Change::Nothing
+                    }
+                    _ => Change::Nothing,
+                }
+            }
+            (Object::XdgOutput(output), opcode) => {
+                let output = self.outputs.entry(output).or_default();
+                let logical = output.logical.get_or_insert((0, 0, 0, 0));
+                match opcode {
+                    0 => {
+                        (logical.0, logical.1) = (args.int()?, args.int()?);
+                        Change::Nothing
+                    }
+                    1 => {
+                        (logical.2, logical.3) = (args.int()?, args.int()?);
+                        Change::Nothing
+                    }
+                    2 => Change::Monitors,
+                    3 => {
+                        let name = args.string()?;
+                        if output.name.is_empty() {
+                            output.name = name;
+                        }
+                        Change::Nothing
+                    }
+                    _ => Change::Nothing,
+                }
+            }
+            (Object::ToplevelManager, 0) => {
+                let toplevel = args.uint()?;
+                self.objects.insert(toplevel, Object::Toplevel);
+                self.toplevels.insert(toplevel, Toplevel::default());
+                Change::Nothing
+            }
+            (Object::Toplevel, opcode) => {
+                let toplevel = self.toplevels.entry(id).or_default();
+                match opcode {
+                    2 => {
+                        let output = args.uint()?;
+                        toplevel.outputs.push(output);
+                        toplevel.hidden_on.retain(|&o| o != output);
+                        Change::Nothing
+                    }
+                    3 => {
+                        let output = args.uint()?;
+                        toplevel.outputs.retain(|&o| o != output);
+                        Change::Nothing
+                    }
+                    4 => {
+                        let states: Vec<u32> = args
+                            .array()?
+                            .chunks_exact(4)
+                            .map(|c| u32::from_ne_bytes(c.try_into().unwrap()))
+                            .collect();
+                        toplevel.minimized = states.contains(&1);
+                        let activated = states.contains(&2);
+                        // Switching to a workspace focuses one of its windows
+                        if activated && !toplevel.activated {
+                            toplevel.hidden_on.clear();
+                        }
+                        toplevel.activated = activated;
+                        Change::Nothing
+                    }
+                    5 => Change::Windows,
+                    6 => {
+                        self.toplevels.remove(&id);
+                        self.objects.remove(&id);
+                        self.conn.send(id, 7, &[])?;
+                        Change::Windows
+                    }
+                    _ => Change::Nothing,
+                }
+            }
+            (Object::WorkspaceManager, 0) => {
+                let group = args.uint()?;
+                self.objects.insert(group, Object::WorkspaceGroup);
+                self.groups.insert(group, Group::default());
+                Change::Nothing
+            }
+            (Object::WorkspaceManager, 1) => {
+                let workspace = args.uint()?;
+                self.objects.insert(workspace, Object::Workspace);
+                self.workspaces.insert(workspace, Workspace::default());
+                Change::Nothing
+            }
+            (Object::WorkspaceManager, 2) => {
+                self.switch_workspaces();
+                Change::Monitors
+            }
+            (Object::WorkspaceGroup, opcode) => {
+                let group = self.groups.entry(id).or_default();
+                match opcode {
+                    1 => group.outputs.push(args.uint()?),
+                    2 => {
+                        let output = args.uint()?;
+                        group.outputs.retain(|&o| o != output);
+                    }
+                    3 => group.workspaces.push(args.uint()?),
+                    4 => {
+                        let workspace = args.uint()?;
+                        group.workspaces.retain(|&w| w != workspace);
+                    }
+                    5 => {
+                        self.groups.remove(&id);
+                        self.objects.remove(&id);
+                    }
+                    _ => {}
+                }
+                Change::Nothing
+            }
+            (Object::Workspace, opcode) => {
+                let workspace = self.workspaces.entry(id).or_default();
+                match opcode {
+                    1 => workspace.name = args.string()?,
+                    3 => workspace.active = args.uint()? & 1 != 0,
+                    5 => {
+                        self.workspaces.remove(&id);
+                        self.objects.remove(&id);
+                    }
+                    _ => {}
+                }
+                Change::Nothing
+            }
+            _ => Change::Nothing,
+        };
+        Ok(change)
+    }
+
+    /// The active ext-workspace shown on an output
+    fn active_workspace(&self, output: u32) -> Option<u32> {
+        self.groups
+            .values()
+            .filter(|group| group.outputs.contains(&output))
+            .flat_map(|group| &group.workspaces)
+            .copied()
+            .find(|id| self.workspaces.get(id).is_some_and(|w| w.active))
+    }
+
+    /// Hides the toplevels of every output whose active workspace changed, until they show up
+    /// there again
+    fn switch_workspaces(&mut self) {
+        let outputs: Vec<u32> = self.outputs.keys().copied().collect();
+        for output in outputs {
+            let active = self.active_workspace(output);
+            let previous = match active {
+                Some(workspace) => self.active.insert(output, workspace),
+                None => self.active.remove(&output),
+            };
+            // The first state the compositor sends is not a switch
+            if previous.is_none() || previous == active {
+                continue;
+            }
+            for toplevel in self.toplevels.values_mut() {
+                if toplevel.outputs.contains(&output) && !toplevel.hidden_on.contains(&output) {
+                    toplevel.hidden_on.push(output);
+                }
+            }
+        }
+    }
+
+    fn monitors(&self) -> Vec<Monitor> {
+        self.outputs
+            .iter()
+            .filter(|(_, output)| !output.name.is_empty())
+            .map(|(&id, output)| {
+                let active_workspace = WorkspaceRef {
+                    id: 0,
+                    name: self
+                        .active_workspace(id)
+                        .and_then(|workspace| self.workspaces.get(&workspace))
+                        .map(|workspace| workspace.name.clone())
+                        .unwrap_or_default(),
+                };
+                let name = output.name.clone();
+                // xdg-output gives the final layout, otherwise it is worked out from the mode
+                let monitor = match output.logical {
+                    Some((x, y, width, height)) => Monitor::logical(name, x, y, width, height),
+                    None => Monitor {
+                        scale: output.scale.max(1) as f64,
+                        transform: output.transform as u8,
+                        ..Monitor::logical(name, output.x, output.y, output.width, output.height)
+                    },
+                };
+                Monitor {
+                    active_workspace,
+                    ..monitor
+                }
+            })
+            .collect()
+    }
+
+    /// An output shows windows when a toplevel that is not minimized is on it.
+    /// The protocol does not say which workspace a toplevel is on, so compositors that keep
+    /// hidden workspaces on the output would count their windows too. With ext-workspace, an
+    /// output that switched workspaces is taken as empty until a toplevel enters it again or
+    /// gets activated, as happens to the windows of the workspace switched to.
+    fn outputs(&self) -> Outputs {
+        let name = |output: &u32| {
+            self.outputs
+                .get(output)
+                .map(|o| o.name.clone())
+                .filter(|n| !n.is_empty())
+        };
+        Outputs {
+            focused: self
+                .toplevels
+                .values()
+                .find(|t| t.activated)
+                .and_then(|t| t.outputs.first())
+                .and_then(name),
+            windows: self
+                .outputs
+                .keys()
+                .filter_map(|id| {
+                    let shown = self.toplevels.values().any(|t| {
+                        !t.minimized && t.outputs.contains(id) && !t.hidden_on.contains(id)
+                    });
+                    Some((name(id)?, shown))
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A session talking to nothing, the requests it sends are dropped
+    fn session() -> (Session, UnixStream) {
+        let (client, server) = UnixStream::pair().unwrap();
+        let mut session = Session::new(client).unwrap();
+        let registry = session.conn.new_id();
+        session.objects.insert(registry, Object::Registry);
+        (session, server)
+    }
+
+    /// Encodes an event the way the compositor would, by sending it as a request
+    fn event(object: u32, opcode: u16, args: &[Arg]) -> Message {
+        let (a, b) = UnixStream::pair().unwrap();
+        let mut writer = Connection::new(a).unwrap();
+        writer.send(object, opcode, args).unwrap();
+        Connection::new(b).unwrap().read().unwrap()
+    }
+
+    fn dispatch(session: &mut Session, object: u32, opcode: u16, args: &[Arg]) -> Change {
+        session.dispatch(event(object, opcode, args)).unwrap()
+    }
+
+    #[test]
+    fn strings_are_padded_and_read_back() {
+        let message = event(5, 3, &[Arg::Str("DP-1"), Arg::Uint(7), Arg::Str("")]);
+        assert_eq!(message.object, 5);
+        assert_eq!(message.opcode, 3);
+        // 4 + "DP-1\0" padded to 8, 4, then 4 + "\0" padded to 4
+        assert_eq!(message.body.len(), 24);
+        let mut args = Args(&message.body);
+        assert_eq!(args.string().unwrap(), "DP-1");
+        assert_eq!(args.uint().unwrap(), 7);
+        assert_eq!(args.string().unwrap(), "");
+        assert!(args.uint().is_err());
+    }
+
+    #[test]
+    fn file_descriptors_travel_beside_the_request() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let mut writer = Connection::new(a).unwrap();
+        let file = std::fs::File::open("/dev/null").unwrap();
+        writer
+            .send_fd(3, 0, &[Arg::Id(4), Arg::Int(-1)], file.as_raw_fd())
+            .unwrap();
+
+        let mut body = [0u8; 16];
+        let mut iov = libc::iovec {
+            iov_base: body.as_mut_ptr() as *mut libc::c_void,
+            iov_len: body.len(),
+        };
+        let mut control = [0u8; 64];
+        let mut header: libc::msghdr = unsafe { std::mem::zeroed() };
+        header.msg_iov = &mut iov;
+        header.msg_iovlen = 1;
+        header.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        header.msg_controllen = control.len() as _;
+        let received = unsafe { libc::recvmsg(b.as_raw_fd(), &mut header, 0) };
+        assert_eq!(received, 16);
+        let cmsg = unsafe { libc::CMSG_FIRSTHDR(&header) };
+        assert!(!cmsg.is_null());
+        assert_eq!(unsafe { (*cmsg).cmsg_type }, libc::SCM_RIGHTS);
+        let fd = unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd) };
+        assert!(fd > 2 && fd != file.as_raw_fd());
+        unsafe { libc::close(fd) };
+        assert_eq!(i32::from_ne_bytes(body[12..].try_into().unwrap()), -1);
+    }
+
+    /// Two outputs bound through the registry, named and sized
+    fn with_outputs(session: &mut Session) -> (u32, u32) {
+        dispatch(
+            session,
+            2,
+            0,
+            &[Arg::Uint(10), Arg::Str("wl_output"), Arg::Uint(4)],
+        );
+        dispatch(
+            session,
+            2,
+            0,
+            &[Arg::Uint(11), Arg::Str("wl_output"), Arg::Uint(4)],
+        );
+        let (left, right) = (session.output_globals[&10], session.output_globals[&11]);
+        dispatch(session, left, 4, &[Arg::Str("DP-1")]);
+        dispatch(session, right, 4, &[Arg::Str("DP-2")]);
+        (left, right)
+    }
+
+    #[test]
+    fn toplevels_make_their_output_occupied() {
+        let (mut session, _server) = session();
+        let (left, right) = with_outputs(&mut session);
+        dispatch(
+            &mut session,
+            2,
+            0,
+            &[
+                Arg::Uint(12),
+                Arg::Str("zwlr_foreign_toplevel_manager_v1"),
+                Arg::Uint(3),
+            ],
+        );
+        assert!(session.has_toplevels);
+        let manager = session.conn.next_id - 1;
+
+        let toplevel = 0xff00_0001;
+        dispatch(&mut session, manager, 0, &[Arg::Id(toplevel)]);
+        dispatch(&mut session, toplevel, 2, &[Arg::Id(left)]);
+        state(&mut session, toplevel, true);
+        assert_eq!(dispatch(&mut session, toplevel, 5, &[]), Change::Windows);
+
+        let outputs = session.outputs();
+        assert_eq!(outputs.focused.as_deref(), Some("DP-1"));
+        assert!(outputs.windows["DP-1"]);
+        assert!(!outputs.windows["DP-2"]);
+
+        dispatch(&mut session, toplevel, 3, &[Arg::Id(left)]);
+        dispatch(&mut session, toplevel, 2, &[Arg::Id(right)]);
+        assert!(session.outputs().windows["DP-2"]);
+
+        assert_eq!(dispatch(&mut session, toplevel, 6, &[]), Change::Windows);
+        assert!(!session.outputs().windows["DP-2"]);
+    }
+
+    #[test]
+    fn xdg_output_gives_the_logical_layout() {
+        let (mut session, _server) = session();
+        let (left, _) = with_outputs(&mut session);
+        dispatch(
+            &mut session,
+            left,
+            0,
+            &[
+                Arg::Uint(0),
+                Arg::Uint(0),
+                Arg::Uint(300),
+                Arg::Uint(190),
+                Arg::Uint(0),
+                Arg::Str("BOE"),
+                Arg::Str("panel"),
+                Arg::Uint(0),
+            ],
+        );
+        dispatch(
+            &mut session,
+            left,
+            1,
+            &[
+                Arg::Uint(1),
+                Arg::Uint(2880),
+                Arg::Uint(1800),
+                Arg::Uint(60000),
+            ],
+        );
+        dispatch(&mut session, left, 3, &[Arg::Uint(2)]);
+        let monitor = session
+            .monitors()
+            .into_iter()
+            .find(|m| m.name == "DP-1")
+            .unwrap();
+        assert_eq!(monitor.bounds().width, 1440);
+
+        dispatch(
+            &mut session,
+            2,
+            0,
+            &[
+                Arg::Uint(13),
+                Arg::Str("zxdg_output_manager_v1"),
+                Arg::Uint(3),
+            ],
+        );
+        let xdg_output = session
+            .objects
+            .iter()
+            .find(|(_, kind)| **kind == Object::XdgOutput(left))
+            .map(|(id, _)| *id)
+            .unwrap();
+        dispatch(
+            &mut session,
+            xdg_output,
+            1,
+            &[Arg::Uint(1920), Arg::Uint(1200)],
+        );
+        let monitor = session
+            .monitors()
+            .into_iter()
+            .find(|m| m.name == "DP-1")
+            .unwrap();
+        assert_eq!(
+            (monitor.bounds().width, monitor.bounds().height),
+            (1920, 1200)
+        );
+    }
+
+    #[test]
+    fn removed_outputs_disappear() {
+        let (mut session, _server) = session();
+        with_outputs(&mut session);
+        assert_eq!(
+            dispatch(&mut session, 2, 1, &[Arg::Uint(10)]),
+            Change::Monitors
+        );
+        let names: Vec<String> = session.monitors().into_iter().map(|m| m.name).collect();
+        assert_eq!(names, vec!["DP-2".to_string()]);
+    }
+
+    /// The state array of a toplevel, `activated` or nothing
+    fn state(session: &mut Session, toplevel: u32, activated: bool) {
+        let mut body = 4u32.to_ne_bytes().to_vec();
+        body.extend_from_slice(&if activated { 2u32 } else { 0 }.to_ne_bytes());
+        let message = Message {
+            object: toplevel,
+            opcode: 4,
+            body,
+        };
+        session.dispatch(message).unwrap();
+    }
+
+    #[test]
+    fn switched_workspaces_are_empty_until_their_windows_show_up() {
+        let (mut session, _server) = session();
+        let (left, _) = with_outputs(&mut session);
+        for (name, interface) in [
+            (12, "zwlr_foreign_toplevel_manager_v1"),
+            (14, "ext_workspace_manager_v1"),
+        ] {
+            dispatch(
+                &mut session,
+                2,
+                0,
+                &[Arg::Uint(name), Arg::Str(interface), Arg::Uint(1)],
+            );
+        }
+        let (toplevels, workspaces) = (session.conn.next_id - 2, session.conn.next_id - 1);
+        let (group, one, two) = (0xff00_0010, 0xff00_0011, 0xff00_0012);
+        dispatch(&mut session, workspaces, 0, &[Arg::Id(group)]);
+        dispatch(&mut session, workspaces, 1, &[Arg::Id(one)]);
+        dispatch(&mut session, workspaces, 1, &[Arg::Id(two)]);
+        dispatch(&mut session, group, 1, &[Arg::Id(left)]);
+        dispatch(&mut session, group, 3, &[Arg::Id(one)]);
+        dispatch(&mut session, group, 3, &[Arg::Id(two)]);
+        dispatch(&mut session, one, 1, &[Arg::Str("1")]);
+        dispatch(&mut session, two, 1, &[Arg::Str("2")]);
+        dispatch(&mut session, one, 3, &[Arg::Uint(1)]);
+        dispatch(&mut session, workspaces, 2, &[]);
+
+        let (first, second) = (0xff00_0001, 0xff00_0002);
+        for toplevel in [first, second] {
+            dispatch(&mut session, toplevels, 0, &[Arg::Id(toplevel)]);
+            dispatch(&mut session, toplevel, 2, &[Arg::Id(left)]);
+        }
+        state(&mut session, first, true);
+        assert!(session.outputs().windows["DP-1"]);
+
+        // An empty workspace: the windows stay on the output, but nothing gets activated
+        dispatch(&mut session, one, 3, &[Arg::Uint(0)]);
+        dispatch(&mut session, two, 3, &[Arg::Uint(1)]);
+        assert_eq!(dispatch(&mut session, workspaces, 2, &[]), Change::Monitors);
+        state(&mut session, first, false);
+        assert!(!session.outputs().windows["DP-1"]);
+        let monitor = session.monitors().into_iter().find(|m| m.name == "DP-1");
+        assert_eq!(monitor.unwrap().active_workspace.name, "2");
+
+        // Back to the first one, whose window gets the focus again
+        dispatch(&mut session, two, 3, &[Arg::Uint(0)]);
+        dispatch(&mut session, one, 3, &[Arg::Uint(1)]);
+        dispatch(&mut session, workspaces, 2, &[]);
+        assert!(!session.outputs().windows["DP-1"]);
+        state(&mut session, first, true);
+        assert!(session.outputs().windows["DP-1"]);
+
+        // Windows entering the output count right away
+        dispatch(&mut session, one, 3, &[Arg::Uint(0)]);
+        dispatch(&mut session, two, 3, &[Arg::Uint(1)]);
+        dispatch(&mut session, workspaces, 2, &[]);
+        assert!(!session.outputs().windows["DP-1"]);
+        dispatch(&mut session, second, 3, &[Arg::Id(left)]);
+        dispatch(&mut session, second, 2, &[Arg::Id(left)]);
+        assert!(session.outputs().windows["DP-1"]);
+    }
+
+    #[test]
+    fn protocol_errors_end_the_session() {
+        let (mut session, _server) = session();
+        let error = event(
+            DISPLAY,
+            0,
+            &[Arg::Id(2), Arg::Uint(1), Arg::Str("invalid object")],
+        );
+        assert!(session.dispatch(error).is_err());
+    }
+}
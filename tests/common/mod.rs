@@ -0,0 +1,331 @@
+//! A fake Hyprland and fake Waybar processes, to run the daemon end to end without either.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::{fs::PermissionsExt, net::UnixListener, net::UnixStream},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long to wait for something the daemon should do
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+const SIGNATURE: &str = "test";
+
+/// A temporary `XDG_RUNTIME_DIR`, removed when dropped
+pub struct RuntimeDir {
+    pub path: PathBuf,
+    id: String,
+}
+
+impl RuntimeDir {
+    pub fn new() -> RuntimeDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let id = format!(
+            "{}x{}",
+            std::process::id() % 100_000,
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(format!("waybar_auto_hide-{id}"));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        RuntimeDir { path, id }
+    }
+
+    /// The process name of a fake Waybar, unique to the test.
+    /// Kept within the 15 bytes of `/proc/<pid>/comm`.
+    pub fn process_name(&self, bar: &str) -> String {
+        format!("fb{}{bar}", self.id)
+    }
+}
+
+impl Drop for RuntimeDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Serves scripted replies on `.socket.sock` and sends events on `.socket2.sock`
+pub struct MockHyprland {
+    replies: Arc<Mutex<HashMap<String, String>>>,
+    /// How many times each request was made
+    requests: Arc<Mutex<HashMap<String, usize>>>,
+    listeners: Arc<Mutex<Vec<UnixStream>>>,
+}
+
+impl MockHyprland {
+    pub fn start(runtime: &RuntimeDir) -> MockHyprland {
+        let dir = runtime.path.join("hypr").join(SIGNATURE);
+        fs::create_dir_all(&dir).unwrap();
+        // Our own pid keeps the instance alive for as long as the test runs
+        fs::write(
+            dir.join("hyprland.lock"),
+            format!("{}\n", std::process::id()),
+        )
+        .unwrap();
+
+        let replies: Arc<Mutex<HashMap<String, String>>> = Arc::default();
+        let requests: Arc<Mutex<HashMap<String, usize>>> = Arc::default();
+        let queries = UnixListener::bind(dir.join(".socket.sock")).unwrap();
+        {
+            let (replies, requests) = (replies.clone(), requests.clone());
+            thread::spawn(move || {
+                for mut stream in queries.incoming().map_while(Result::ok) {
+                    let mut request = [0u8; 256];
+                    let Ok(len) = stream.read(&mut request) else {
+                        continue;
+                    };
+                    let request = String::from_utf8_lossy(&request[..len]).into_owned();
+                    *requests.lock().unwrap().entry(request.clone()).or_default() += 1;
+                    let reply = replies
+                        .lock()
+                        .unwrap()
+                        .get(&request)
+                        .cloned()
+                        .unwrap_or_else(|| "unknown request".to_string());
+                    let _ = stream.write_all(reply.as_bytes());
+                }
+            });
+        }
+
+        let listeners: Arc<Mutex<Vec<UnixStream>>> = Arc::default();
+        let events = UnixListener::bind(dir.join(".socket2.sock")).unwrap();
+        {
+            let listeners = listeners.clone();
+            thread::spawn(move || {
+                for stream in events.incoming().map_while(Result::ok) {
+                    listeners.lock().unwrap().push(stream);
+                }
+            });
+        }
+
+        MockHyprland {
+            replies,
+            requests,
+            listeners,
+        }
+    }
+
+    /// Sets the reply to a request such as `j/monitors`
+    pub fn reply(&self, request: &str, json: &str) {
+        self.replies
+            .lock()
+            .unwrap()
+            .insert(request.to_string(), json.to_string());
+    }
+
+    /// How many times a request such as `j/clients` was made so far
+    pub fn requests(&self, request: &str) -> usize {
+        self.requests
+            .lock()
+            .unwrap()
+            .get(request)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Waits until the daemon follows the event socket
+    pub fn wait_for_listener(&self) {
+        let deadline = Instant::now() + TIMEOUT;
+        while self.listeners.lock().unwrap().is_empty() {
+            assert!(
+                Instant::now() < deadline,
+                "the daemon never connected to .socket2.sock"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Sends one `EVENT>>DATA` line to every listener
+    pub fn emit(&self, line: &str) {
+        self.listeners
+            .lock()
+            .unwrap()
+            .retain_mut(|stream| stream.write_all(format!("{line}\n").as_bytes()).is_ok());
+    }
+}
+
+/// A shell script posing as Waybar: it is found by its process name, started with `-c <config>`,
+/// and prints the name of every signal it receives
+pub struct FakeWaybar {
+    child: Child,
+    signals: Receiver<String>,
+}
+
+impl FakeWaybar {
+    pub fn start(runtime: &RuntimeDir, name: &str, waybar_config: &str) -> FakeWaybar {
+        let config = runtime.path.join(format!("{name}.jsonc"));
+        fs::write(&config, waybar_config).unwrap();
+        let script = runtime.path.join(runtime.process_name(name));
+        fs::write(
+            &script,
+            "#!/bin/sh\n\
+             trap 'echo USR1' USR1\n\
+             trap 'echo USR2' USR2\n\
+             echo ready\n\
+             while :; do sleep 0.02; done\n",
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut child = Command::new(&script)
+            .arg("-c")
+            .arg(&config)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let (tx, signals) = mpsc::channel();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        thread::spawn(move || {
+            for line in stdout.lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let waybar = FakeWaybar { child, signals };
+        assert_eq!(waybar.next(TIMEOUT).as_deref(), Some("ready"));
+        waybar
+    }
+
+    fn next(&self, timeout: Duration) -> Option<String> {
+        self.signals.recv_timeout(timeout).ok()
+    }
+
+    /// Waits for the next signal, which has to be `signal`
+    pub fn expect(&self, signal: &str) {
+        assert_eq!(self.next(TIMEOUT).as_deref(), Some(signal));
+    }
+
+    /// Checks that no signal arrives for a while
+    pub fn expect_none(&self) {
+        assert_eq!(self.next(Duration::from_millis(400)), None);
+    }
+}
+
+impl Drop for FakeWaybar {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The daemon, running against the runtime directory of a test
+pub struct Daemon {
+    child: Child,
+    runtime: PathBuf,
+}
+
+impl Daemon {
+    /// Starts the daemon with a config that only recognizes the fake Waybar processes of this test
+    pub fn start(runtime: &RuntimeDir, config: &str) -> Daemon {
+        let path = write_config(runtime, config);
+        let child = command(&runtime.path)
+            .arg("--config")
+            .arg(&path)
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        Daemon {
+            child,
+            runtime: runtime.path.clone(),
+        }
+    }
+
+    /// Replaces the config file, which the daemon reloads on its own
+    pub fn reload(&self, runtime: &RuntimeDir, config: &str) {
+        write_config(runtime, config);
+    }
+
+    /// Runs a client command such as `status` against this daemon, waiting for it to answer
+    pub fn command(&self, args: &[&str]) -> String {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let output = command(&self.runtime).args(args).output().unwrap();
+            if output.status.success() {
+                return String::from_utf8(output.stdout).unwrap();
+            }
+            assert!(
+                Instant::now() < deadline,
+                "the daemon never answered {args:?}"
+            );
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Writes a config that only recognizes the fake Waybar processes of this test,
+/// in the `[waybar]` section of `config` if it has one
+fn write_config(runtime: &RuntimeDir, config: &str) -> PathBuf {
+    let path = runtime.path.join("config.toml");
+    let names = format!(
+        "process_names = [{:?}, {:?}]\n",
+        runtime.process_name("left"),
+        runtime.process_name("right"),
+    );
+    let config = match config.split_once("[waybar]\n") {
+        Some((before, after)) => format!("{before}[waybar]\n{names}{after}"),
+        None => format!("{config}\n[waybar]\n{names}"),
+    };
+    fs::write(&path, config).unwrap();
+    path
+}
+
+/// The binary, in an environment where only the mock Hyprland can be found
+fn command(runtime: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_waybar_auto_hide"));
+    command
+        .env("XDG_RUNTIME_DIR", runtime)
+        .env("XDG_CONFIG_HOME", runtime)
+        .env("HYPRLAND_INSTANCE_SIGNATURE", SIGNATURE)
+        .env_remove("NIRI_SOCKET")
+        .env_remove("SWAYSOCK")
+        .env_remove("I3SOCK")
+        .env_remove("WAYLAND_DISPLAY");
+    command
+}
+
+/// A monitor as `j/monitors` describes it
+pub fn monitor(name: &str, x: i32, workspace: i64, focused: bool) -> String {
+    format!(
+        r#"{{"name": "{name}", "x": {x}, "y": 0, "width": 1920, "height": 1080, "scale": 1.0,
+            "transform": 0, "focused": {focused}, "activeWorkspace": {{"id": {workspace}, "name": "{workspace}"}},
+            "specialWorkspace": {{"id": 0, "name": ""}}, "reserved": [0, 30, 0, 0]}}"#
+    )
+}
+
+/// A workspace as `j/workspaces` describes it
+pub fn workspace(id: i64, monitor: &str) -> String {
+    format!(r#"{{"id": {id}, "name": "{id}", "monitor": "{monitor}"}}"#)
+}
+
+/// A window as `j/clients` describes it
+pub fn client(address: &str, workspace: i64) -> String {
+    format!(
+        r#"{{"address": "{address}", "mapped": true, "workspace": {{"id": {workspace}, "name": "{workspace}"}}}}"#
+    )
+}
+
+/// A floating window at the given position, 400 by 300 pixels
+pub fn floating_client(address: &str, workspace: i64, x: i32, y: i32) -> String {
+    format!(
+        r#"{{"address": "{address}", "mapped": true, "workspace": {{"id": {workspace}, "name": "{workspace}"}},
+            "floating": true, "at": [{x}, {y}], "size": [400, 300]}}"#
+    )
+}
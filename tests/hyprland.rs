@@ -0,0 +1,270 @@
+//! The whole daemon against a mock Hyprland, checking the signals fake Waybar processes receive.
+
+mod common;
+
+use common::{
+    Daemon, FakeWaybar, MockHyprland, RuntimeDir, client, floating_client, monitor, workspace,
+};
+
+/// Hides on SIGUSR1 and shows on SIGUSR2, like the defaults of the daemon
+const WAYBAR_CONFIG: &str =
+    r#"{"position": "top", "height": 30, "on-sigusr1": "hide", "on-sigusr2": "show"}"#;
+
+/// One monitor showing workspace 1, with the cursor in the middle of the screen
+fn single_monitor(runtime: &RuntimeDir, clients: &[String]) -> MockHyprland {
+    let hyprland = MockHyprland::start(runtime);
+    hyprland.reply("j/monitors", &format!("[{}]", monitor("DP-1", 0, 1, true)));
+    hyprland.reply(
+        "j/workspaces",
+        &format!("[{}, {}]", workspace(1, "DP-1"), workspace(2, "DP-1")),
+    );
+    hyprland.reply("j/clients", &format!("[{}]", clients.join(", ")));
+    hyprland.reply("j/cursorpos", r#"{"x": 960, "y": 540}"#);
+    hyprland
+}
+
+#[test]
+fn windows_opening_and_closing() {
+    let runtime = RuntimeDir::new();
+    let hyprland = single_monitor(&runtime, &[]);
+    let waybar = FakeWaybar::start(&runtime, "left", WAYBAR_CONFIG);
+    let _daemon = Daemon::start(&runtime, "");
+    hyprland.wait_for_listener();
+
+    // The workspace is empty, so the bar stays as Waybar started it
+    waybar.expect_none();
+
+    hyprland.emit("openwindow>>5f00a0,1,kitty,kitty");
+    waybar.expect("USR1");
+    hyprland.emit("activewindowv2>>5f00a0");
+    waybar.expect_none();
+    hyprland.emit("closewindow>>5f00a0");
+    waybar.expect("USR2");
+}
+
+#[test]
+fn switching_to_an_empty_workspace() {
+    let runtime = RuntimeDir::new();
+    let hyprland = single_monitor(&runtime, &[client("0x5f00a0", 1)]);
+    let waybar = FakeWaybar::start(&runtime, "left", WAYBAR_CONFIG);
+    let _daemon = Daemon::start(&runtime, "");
+    hyprland.wait_for_listener();
+
+    waybar.expect("USR1");
+    hyprland.emit("workspacev2>>2,2");
+    waybar.expect("USR2");
+    hyprland.emit("workspacev2>>1,1");
+    waybar.expect("USR1");
+}
+
+#[test]
+fn debounced_workspace_switches() {
+    let runtime = RuntimeDir::new();
+    let hyprland = single_monitor(&runtime, &[client("0x5f00a0", 1)]);
+    let waybar = FakeWaybar::start(&runtime, "left", WAYBAR_CONFIG);
+    let _daemon = Daemon::start(&runtime, "[delays]\ndebounce_ms = 300");
+    hyprland.wait_for_listener();
+    waybar.expect("USR1");
+
+    // Passing through the empty workspace is too quick to show the bar
+    hyprland.emit("workspacev2>>2,2");
+    hyprland.emit("workspacev2>>1,1");
+    waybar.expect_none();
+
+    hyprland.emit("workspacev2>>2,2");
+    waybar.expect("USR2");
+}
+
+#[test]
+fn workspace_switches_peek() {
+    let runtime = RuntimeDir::new();
+    let hyprland = single_monitor(&runtime, &[client("0x5f00a0", 1), client("0x5f00b0", 2)]);
+    let waybar = FakeWaybar::start(&runtime, "left", WAYBAR_CONFIG);
+    let _daemon = Daemon::start(&runtime, "[peek]\nworkspace_switch_ms = 300");
+    hyprland.wait_for_listener();
+    waybar.expect("USR1");
+
+    // Both workspaces have windows, so only the peek shows the bar
+    hyprland.emit("workspacev2>>2,2");
+    waybar.expect("USR2");
+    waybar.expect("USR1");
+}
+
+#[test]
+fn intellihide_follows_floating_windows() {
+    let runtime = RuntimeDir::new();
+    let hyprland = single_monitor(&runtime, &[floating_client("0x5f00a0", 1, 760, 390)]);
+    let waybar = FakeWaybar::start(&runtime, "left", WAYBAR_CONFIG);
+    let _daemon = Daemon::start(&runtime, "[windows]\nintellihide = true");
+    hyprland.wait_for_listener();
+
+    // The dialog is far from the bar
+    waybar.expect_none();
+
+    // Hyprland reports no drags, the next window event brings the new geometry
+    hyprland.reply(
+        "j/clients",
+        &format!("[{}]", floating_client("0x5f00a0", 1, 760, 10)),
+    );
+    hyprland.emit("fullscreen>>0");
+    waybar.expect("USR1");
+
+    hyprland.reply(
+        "j/clients",
+        &format!("[{}]", floating_client("0x5f00a0", 1, 760, 390)),
+    );
+    hyprland.emit("fullscreen>>0");
+    waybar.expect("USR2");
+}
+
+#[test]
+fn window_geometry_is_only_queried_for_intellihide() {
+    let runtime = RuntimeDir::new();
+    let hyprland = single_monitor(&runtime, &[]);
+    let waybar = FakeWaybar::start(&runtime, "left", WAYBAR_CONFIG);
+    let _daemon = Daemon::start(&runtime, "");
+    hyprland.wait_for_listener();
+
+    hyprland.emit("openwindow>>5f00a0,1,kitty,kitty");
+    waybar.expect("USR1");
+    let queried = hyprland.requests("j/clients");
+    hyprland.emit("movewindowv2>>5f00a0,1,1");
+    hyprland.emit("fullscreen>>1");
+    hyprland.emit("changefloatingmode>>5f00a0,1");
+    waybar.expect_none();
+    assert_eq!(hyprland.requests("j/clients"), queried);
+}
+
+#[test]
+fn cursor_reveals_the_bar() {
+    let runtime = RuntimeDir::new();
+    let hyprland = single_monitor(&runtime, &[client("0x5f00a0", 1)]);
+    let waybar = FakeWaybar::start(&runtime, "left", WAYBAR_CONFIG);
+    let _daemon = Daemon::start(&runtime, "");
+    hyprland.wait_for_listener();
+    waybar.expect("USR1");
+
+    hyprland.reply("j/cursorpos", r#"{"x": 960, "y": 0}"#);
+    waybar.expect("USR2");
+    // Still over the 30 pixels of the bar
+    hyprland.reply("j/cursorpos", r#"{"x": 960, "y": 20}"#);
+    waybar.expect_none();
+    hyprland.reply("j/cursorpos", r#"{"x": 960, "y": 540}"#);
+    waybar.expect("USR1");
+}
+
+#[test]
+fn bars_follow_their_own_monitor() {
+    let runtime = RuntimeDir::new();
+    let hyprland = MockHyprland::start(&runtime);
+    hyprland.reply(
+        "j/monitors",
+        &format!(
+            "[{}, {}]",
+            monitor("DP-1", 0, 1, true),
+            monitor("DP-2", 1920, 2, false)
+        ),
+    );
+    hyprland.reply(
+        "j/workspaces",
+        &format!("[{}, {}]", workspace(1, "DP-1"), workspace(2, "DP-2")),
+    );
+    hyprland.reply("j/clients", "[]");
+    hyprland.reply("j/cursorpos", r#"{"x": 960, "y": 540}"#);
+
+    let left = FakeWaybar::start(
+        &runtime,
+        "left",
+        r#"{"output": "DP-1", "on-sigusr1": "hide", "on-sigusr2": "show"}"#,
+    );
+    let right = FakeWaybar::start(
+        &runtime,
+        "right",
+        r#"{"output": "DP-2", "on-sigusr1": "hide", "on-sigusr2": "show"}"#,
+    );
+    let _daemon = Daemon::start(
+        &runtime,
+        "[[bars]]\nname = \"left\"\nconfig = \"left.jsonc\"\n\
+         [[bars]]\nname = \"right\"\nconfig = \"right.jsonc\"",
+    );
+    hyprland.wait_for_listener();
+
+    hyprland.emit("openwindow>>5f00a0,2,mpv,mpv");
+    right.expect("USR1");
+    left.expect_none();
+
+    // The cursor at the top of DP-2 only reveals the bar there
+    hyprland.reply("j/cursorpos", r#"{"x": 2500, "y": 0}"#);
+    right.expect("USR2");
+    left.expect_none();
+}
+
+#[test]
+fn reloads_keep_the_state_of_the_bars() {
+    let runtime = RuntimeDir::new();
+    let hyprland = single_monitor(&runtime, &[client("0x5f00a0", 1)]);
+    let waybar = FakeWaybar::start(&runtime, "left", WAYBAR_CONFIG);
+    let daemon = Daemon::start(&runtime, "");
+    hyprland.wait_for_listener();
+    waybar.expect("USR1");
+
+    // Rebuilds the bars, which must not hide the hidden bar again
+    daemon.reload(&runtime, "[[bars]]\nname = \"main\"");
+    let deadline = std::time::Instant::now() + common::TIMEOUT;
+    while !daemon.command(&["status"]).contains(r#""name":"main""#) {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "the config was never reloaded"
+        );
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    waybar.expect_none();
+}
+
+#[test]
+fn reloads_detect_the_waybar_config_again() {
+    let runtime = RuntimeDir::new();
+    let hyprland = single_monitor(&runtime, &[client("0x5f00a0", 1)]);
+    let waybar = FakeWaybar::start(&runtime, "left", WAYBAR_CONFIG);
+    let daemon = Daemon::start(&runtime, "[waybar]\ndetect_config = false");
+    hyprland.wait_for_listener();
+    waybar.expect("USR1");
+    let status = daemon.command(&["status"]);
+    assert!(status.contains(r#""hide":50"#), "{status}");
+
+    // The height of the bar in the Waybar config becomes the hide threshold
+    daemon.reload(&runtime, "");
+    let deadline = std::time::Instant::now() + common::TIMEOUT;
+    while !daemon.command(&["status"]).contains(r#""hide":30"#) {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "the Waybar config was never detected"
+        );
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    waybar.expect_none();
+}
+
+#[test]
+fn control_commands_override_the_automatic_state() {
+    let runtime = RuntimeDir::new();
+    let hyprland = single_monitor(&runtime, &[client("0x5f00a0", 1)]);
+    let waybar = FakeWaybar::start(&runtime, "left", WAYBAR_CONFIG);
+    let daemon = Daemon::start(&runtime, "[peek]\nworkspace_switch_ms = 60000");
+    hyprland.wait_for_listener();
+    waybar.expect("USR1");
+
+    assert_eq!(daemon.command(&["pin"]).trim(), "ok");
+    waybar.expect("USR2");
+    let status = daemon.command(&["status"]);
+    assert!(status.contains(r#""compositor":"Hyprland""#), "{status}");
+    assert!(status.contains(r#""pinned":true"#), "{status}");
+    assert_eq!(daemon.command(&["unpin"]).trim(), "ok");
+    waybar.expect("USR1");
+
+    // `hide` cuts a workspace switch peek short
+    hyprland.emit("workspacev2>>1,1");
+    waybar.expect("USR2");
+    assert_eq!(daemon.command(&["hide"]).trim(), "ok");
+    waybar.expect("USR1");
+}